@@ -5,6 +5,191 @@ use proc_macro_crate::*;
 use proc_macro::TokenStream;
 use syn::LitStr;
 
+/// Parses `FF`, `?`, `??`, `4?`, `?8` or `value&mask` into a value and mask, the same as skidscan's `SigByte`
+fn parse_byte(token: &str) -> Option<(u8, u8)> {
+	fn nibble(c: u8) -> Option<u8> {
+		(c as char).to_digit(16).map(|nibble| nibble as u8)
+	}
+	fn hex(token: &str) -> Option<u8> {
+		match token.as_bytes() {
+			[hi, lo] => Some(nibble(*hi)? << 4 | nibble(*lo)?),
+			_ => None,
+		}
+	}
+
+	match token.as_bytes() {
+		b"?" | b"??" => Some((0, 0)),
+		[b'?', lo] => Some((nibble(*lo)?, 0x0F)),
+		[hi, b'?'] => Some((nibble(*hi)? << 4, 0xF0)),
+		_ => match token.split_once('&') {
+			Some((value, mask)) => {
+				let (value, mask) = (hex(value)?, hex(mask)?);
+				if value & !mask != 0 {
+					return None;
+				}
+				Some((value, mask))
+			}
+			None => Some((hex(token)?, 0xFF)),
+		},
+	}
+}
+
+/// Parses `[min-max]`, `[len]`, `*{min,max}` or `*{len}` into a gap's bounds, the same as skidscan's `SigElement::Gap`
+fn parse_gap(token: &str) -> Option<(usize, usize)> {
+	let (gap, separator) = token
+		.strip_prefix('[')
+		.and_then(|gap| gap.strip_suffix(']'))
+		.map(|gap| (gap, '-'))
+		.or_else(|| token.strip_prefix("*{").and_then(|gap| gap.strip_suffix('}')).map(|gap| (gap, ',')))?;
+	let (min, max) = gap.split_once(separator).unwrap_or((gap, gap));
+
+	let parse = |len: &str| {
+		if !len.is_empty() && len.bytes().all(|c| c.is_ascii_digit()) {
+			len.parse::<usize>().ok()
+		} else {
+			None
+		}
+	};
+	let (min, max) = (parse(min)?, parse(max)?);
+	if min > max {
+		panic!("Gap `{}` in signature has a minimum greater than its maximum", token);
+	}
+	Some((min, max))
+}
+
+/// Parses `${'name:len}` or `${'name}` into a capture's name and length, the same as skidscan's `SigElement::Capture`
+fn parse_capture(token: &str) -> Option<(&str, usize)> {
+	let capture = token.strip_prefix("${'")?.strip_suffix('}')?;
+	let (name, len) = match capture.split_once(':') {
+		Some((name, len)) => {
+			if len.is_empty() || !len.bytes().all(|c| c.is_ascii_digit()) {
+				panic!("Invalid length in capture `{}` in signature", token);
+			}
+			(name, len.parse().unwrap_or_else(|_| panic!("Invalid length in capture `{}` in signature", token)))
+		}
+		None => (capture, 0),
+	};
+	if name.is_empty() || !name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_') {
+		panic!("Invalid name in capture `{}` in signature, names must be made up of ASCII letters, digits and underscores", token);
+	}
+	Some((name, len))
+}
+
+/// Generates the code for a signature, mirroring the parser of skidscan's `Signature::from_str`
+struct Generator<'a> {
+	s: &'a str,
+	pos: usize,
+	crate_name: String,
+	_obfuscate: bool,
+	added_byte: bool,
+
+	/// The names of the captures that can be part of the same match as the next element
+	captures: Vec<String>,
+}
+impl Generator<'_> {
+	#[inline]
+	fn peek(&self) -> Option<u8> {
+		self.s.as_bytes().get(self.pos).copied()
+	}
+
+	#[inline]
+	fn skip_space(&mut self) {
+		if self.peek() == Some(b' ') {
+			self.pos += 1;
+		}
+	}
+
+	/// Generates a `Signature` of space separated elements until the end of the string, or the end of an alternative if `in_group`
+	fn sequence(&mut self, in_group: bool) -> String {
+		let mut signature = format!("{}Signature::from(vec![", self.crate_name);
+		loop {
+			signature.push_str(&self.element());
+			signature.push(',');
+			match self.peek() {
+				None => break,
+				Some(b' ') => {
+					self.pos += 1;
+					if in_group && matches!(self.peek(), Some(b'|') | Some(b')')) {
+						break;
+					}
+				}
+				Some(b'|') | Some(b')') if in_group => break,
+				Some(c) => panic!("Unexpected `{}` in signature", c as char),
+			}
+		}
+		signature.push_str("])");
+		signature
+	}
+
+	fn element(&mut self) -> String {
+		if self.peek() == Some(b'(') {
+			self.pos += 1;
+			self.skip_space();
+			let mut alternatives = format!("{}SigElement::Alt(vec![", self.crate_name);
+			let before = self.captures.len();
+			let mut captures = Vec::new();
+			loop {
+				alternatives.push_str(&self.sequence(true));
+				alternatives.push(',');
+				captures.extend(self.captures.drain(before..));
+				match self.peek() {
+					Some(b'|') => {
+						self.pos += 1;
+						self.skip_space();
+					}
+					Some(b')') => {
+						self.pos += 1;
+						self.captures.extend(captures);
+						break;
+					}
+					_ => panic!("Unclosed group in signature"),
+				}
+			}
+			alternatives.push_str("])");
+			return alternatives;
+		}
+
+		let rest = &self.s[self.pos..];
+		let token = &rest[..rest.find([' ', '(', ')', '|']).unwrap_or(rest.len())];
+		self.pos += token.len();
+
+		if let Some((name, len)) = parse_capture(token) {
+			if self.captures.iter().any(|capture| capture == name) {
+				panic!("Duplicate capture `{}` in signature", name);
+			}
+			self.captures.push(name.to_string());
+			return format!("{}SigElement::Capture {{ name: \"{}\".to_string(), len: {} }}", self.crate_name, name, len);
+		}
+
+		let crate_name = &self.crate_name;
+		if let Some((min, max)) = parse_gap(token) {
+			return format!("{}SigElement::Gap {{ min: {}, max: {} }}", crate_name, min, max);
+		}
+
+		let (value, mask) = parse_byte(token).unwrap_or_else(|| panic!("Invalid byte `{}` in signature", token));
+		if mask == 0 {
+			return format!("{0}SigElement::Byte({0}SigByte::ANY)", crate_name);
+		}
+		self.added_byte = true;
+
+		#[cfg(feature = "obfuscate")]
+		if self._obfuscate {
+			let obfuscated = |byte: u8| format!("{}obfstr!(\"{}\").parse::<u8>().unwrap()", crate_name, byte);
+			if mask == 0xFF {
+				return format!("{0}SigElement::Byte({0}SigByte::exact({1}))", crate_name, obfuscated(value));
+			} else {
+				return format!("{0}SigElement::Byte({0}SigByte::masked({1}, {2}))", crate_name, obfuscated(value), obfuscated(mask));
+			}
+		}
+
+		if mask == 0xFF {
+			format!("{0}SigElement::Byte({0}SigByte::exact(0x{1:02X}))", crate_name, value)
+		} else {
+			format!("{0}SigElement::Byte({0}SigByte::masked(0x{1:02X}, 0x{2:02X}))", crate_name, value, mask)
+		}
+	}
+}
+
 fn signature_internal(tokens: TokenStream, _obfuscate: bool) -> TokenStream {
 	let tokens = parse_macro_input!(tokens as LitStr).value();
 
@@ -22,47 +207,19 @@ fn signature_internal(tokens: TokenStream, _obfuscate: bool) -> TokenStream {
 		}
 	};
 
-	let mut added_byte = false;
-	let mut first = true;
-
-	let mut signature = format!("{}Signature::from(vec![", crate_name);
-	for byte in trimmed.split(' ') {
-		match (byte.len(), byte) {
-			(1, "?") | (2, "??") => if first {
-				signature.push_str("None::<u8>,");
-			} else {
-				signature.push_str("None,");
-			},
-			(2, _) => {
-				added_byte = true;
-
-				#[cfg(feature = "obfuscate")]
-				if _obfuscate {
-					let byte = u8::from_str_radix(&byte, 16).expect("Invalid byte in signature");
-					signature.push_str(&format!("Some({}obfstr!(\"", crate_name));
-					signature.push_str(&byte.to_string());
-					signature.push_str("\").parse::<u8>().unwrap()),");
-					continue;
-				}
-
-				signature.push_str("Some(0x");
-				signature.push_str(byte);
-				if first {
-					signature.push_str("u8");
-				}
-				signature.push_str("),");
-			},
-			_ => panic!("Invalid byte in signature")
-		}
-		first = false;
-	}
+	let mut generator = Generator {
+		s: trimmed,
+		pos: 0,
+		crate_name,
+		_obfuscate,
+		added_byte: false,
+		captures: Vec::new(),
+	};
+	let signature = generator.sequence(false);
 
-	if signature.is_empty() {
-		panic!("Empty signature")
-	} else if !added_byte {
-		panic!("Signature only contains ?? bytes")
+	if !generator.added_byte {
+		panic!("Signature only contains ?? bytes and gaps")
 	} else {
-		signature.push_str("])");
 		signature.parse().unwrap()
 	}
 }
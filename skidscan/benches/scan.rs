@@ -0,0 +1,108 @@
+//! Compares the scan engine against the byte-by-byte loops it replaced.
+//!
+//! Run with `cargo bench -p skidscan`
+
+use std::hint::black_box;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use skidscan::Signature;
+
+const HAYSTACK_LEN: usize = 64 * 1024 * 1024;
+const ITERATIONS: u32 = 5;
+
+/// The loop `Signature::scan` used before the scan engine
+fn legacy_scan(signature: &[Option<u8>], bytes: &[u8]) -> Option<usize> {
+	let mut iter_bytes = bytes.iter().enumerate();
+	let mut start = 0;
+	let mut i = 0;
+	while i < signature.len() {
+		let (byte_pos, byte) = iter_bytes.next()?;
+		if let Some(sig_byte) = &signature[i] {
+			if sig_byte == byte {
+				i += 1;
+				if start == 0 {
+					start = byte_pos;
+				}
+			} else {
+				start = 0;
+				i = 0;
+			}
+		} else {
+			i += 1;
+		}
+	}
+	Some(start)
+}
+
+/// The loop the module scanners used before the scan engine
+fn legacy_module_find(signature: &[Option<u8>], bytes: &[u8]) -> Option<usize> {
+	let mut data_current = 0;
+	let mut signature_offset = 0;
+	let mut result = None;
+	while data_current < bytes.len() {
+		if signature[signature_offset].is_none() || signature[signature_offset] == Some(bytes[data_current]) {
+			if signature.len() <= signature_offset + 1 {
+				if result.is_some() {
+					return None;
+				}
+				result = Some(data_current - signature_offset);
+				data_current -= signature_offset;
+				signature_offset = 0;
+			} else {
+				signature_offset += 1;
+			}
+		} else {
+			data_current -= signature_offset;
+			signature_offset = 0;
+		}
+		data_current += 1;
+	}
+	result
+}
+
+fn time(name: &str, mut f: impl FnMut() -> Option<usize>) {
+	let mut total = Duration::default();
+	for _ in 0..ITERATIONS {
+		let start = Instant::now();
+		black_box(f());
+		total += start.elapsed();
+	}
+	let per_iter = total / ITERATIONS;
+	println!(
+		"{:<24} {:>10.2?} {:>10.1} MiB/s",
+		name,
+		per_iter,
+		HAYSTACK_LEN as f64 / (1024. * 1024.) / per_iter.as_secs_f64()
+	);
+}
+
+fn main() {
+	// Pseudo-random "code" made mostly of common x86 bytes, with the signature planted at the very end
+	let mut seed = 0x5EED_5CA1_AB1E_F00Du64;
+	let mut haystack: Vec<u8> = (0..HAYSTACK_LEN)
+		.map(|_| {
+			seed ^= seed << 13;
+			seed ^= seed >> 7;
+			seed ^= seed << 17;
+			[0x00, 0x48, 0x8B, 0x89, 0xFF, 0xE8, 0x0F, 0x55, 0xC3, 0xCC][(seed % 10) as usize]
+		})
+		.collect();
+
+	let signature = Signature::from_str("48 8B 05 ?? ?? ?? ?? 48 85 C0 74 ?? 48 8B 80 ?? ?? ?? ?? C3").unwrap();
+	let planted = [
+		0x48, 0x8B, 0x05, 0x11, 0x22, 0x33, 0x44, 0x48, 0x85, 0xC0, 0x74, 0x10, 0x48, 0x8B, 0x80, 0x00, 0x01, 0x00, 0x00, 0xC3,
+	];
+	let at = haystack.len() - planted.len();
+	haystack[at..].copy_from_slice(&planted);
+
+	assert_eq!(signature.scan(&haystack), Some(at));
+
+	// The legacy loops only understand whole-byte wildcards
+	let legacy: Vec<Option<u8>> = signature.iter().map(|element| element.as_byte().and_then(|byte| byte.as_exact())).collect();
+
+	time("Signature::scan", || signature.scan(black_box(&haystack)));
+	time("legacy scan", || legacy_scan(black_box(&legacy), black_box(&haystack)));
+	time("Signature::scan_all", || signature.scan_all(black_box(&haystack)).last());
+	time("legacy module find", || legacy_module_find(black_box(&legacy), black_box(&haystack)));
+}
@@ -0,0 +1,196 @@
+use crate::ModuleSigScanError;
+use std::convert::TryFrom;
+
+/// Types that any bytes are a valid value of, which [`Address::deref`] can read
+///
+/// # Safety
+///
+/// Every bit pattern of the type's size must be a valid value of the type, e.g. integers, or `#[repr(C)]` structs made up of them without padding
+pub unsafe trait Pod: Copy {}
+macro_rules! impl_pod {
+	($($ty:ty),*) => {
+		$(unsafe impl Pod for $ty {})*
+	};
+}
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+unsafe impl<T> Pod for *const T {}
+unsafe impl<T> Pod for *mut T {}
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// A region of memory an [`Address`] can point into
+#[derive(Clone, Copy)]
+pub(crate) struct Region<'a> {
+	/// The address of the first byte of the region
+	pub(crate) address: usize,
+	pub(crate) bytes: &'a [u8],
+}
+impl<'a> Region<'a> {
+	/// Returns the `len` bytes at `address` if they are all in this region
+	#[inline]
+	fn get(&self, address: usize, len: usize) -> Option<&'a [u8]> {
+		let offset = address.checked_sub(self.address)?;
+		self.bytes.get(offset..offset.checked_add(len)?)
+	}
+}
+
+/// An error following an [`Address`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ResolveError {
+	/// Scanning for the signature failed
+	Scan(ModuleSigScanError),
+
+	/// The address, or the `len` bytes at it, aren't in the memory that was scanned
+	OutOfBounds { address: usize, len: usize },
+
+	/// Adding the offset to the address overflowed
+	Overflow { address: usize, offset: i128 },
+}
+impl From<ModuleSigScanError> for ResolveError {
+	#[inline]
+	fn from(error: ModuleSigScanError) -> Self {
+		ResolveError::Scan(error)
+	}
+}
+impl std::fmt::Display for ResolveError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ResolveError::Scan(error) => write!(f, "{}", error),
+			ResolveError::OutOfBounds { address, len: 0 } => write!(f, "address {:#x} is outside of the scanned memory", address),
+			ResolveError::OutOfBounds { address, len } => write!(f, "{} bytes at address {:#x} are outside of the scanned memory", len, address),
+			ResolveError::Overflow { address, offset } => write!(f, "offset {} from address {:#x} overflows", offset, address),
+		}
+	}
+}
+impl std::error::Error for ResolveError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ResolveError::Scan(error) => Some(error),
+			_ => None,
+		}
+	}
+}
+
+/// An address in the memory a signature was found in, which can be followed through displacements and pointers
+///
+/// Every operation checks that the address it results in, or the memory it reads, is still in the memory that was scanned
+/// (e.g. the readable segments of a module), and returns a [`ResolveError`] instead of reading out of bounds.
+///
+/// ```ignore
+/// // E8 rel32 -> the function that is called -> a global that it loads with mov rax, [rip+disp32]
+/// let global = signature!("E8 ? ? ? ? 48 85 C0").scan_module_address("client.so")?.rel32(1, 5)?.add(0x10)?.rel32(3, 7)?.deref::<usize>()?;
+/// ```
+#[derive(Clone)]
+pub struct Address<'a> {
+	address: usize,
+	regions: Vec<Region<'a>>,
+}
+impl<'a> Address<'a> {
+	/// Creates an address in the given regions, which must contain it
+	pub(crate) fn new(address: usize, regions: Vec<Region<'a>>) -> Result<Self, ResolveError> {
+		Address { address: 0, regions }.at(address)
+	}
+
+	/// Creates an address at `offset` in a slice of bytes, which can't be followed outside of the slice
+	#[inline]
+	pub fn in_slice(bytes: &'a [u8], offset: usize) -> Result<Self, ResolveError> {
+		let address = bytes.as_ptr() as usize;
+		Address::new(address.checked_add(offset).ok_or(ResolveError::Overflow { address, offset: offset as i128 })?, vec![Region { address, bytes }])
+	}
+
+	/// The address
+	#[inline]
+	pub fn get(&self) -> usize {
+		self.address
+	}
+
+	/// The address as a pointer
+	#[inline]
+	pub fn as_ptr<T>(&self) -> *mut T {
+		self.address as *mut T
+	}
+
+	/// The offset of the address from the start of the region of memory it is in, e.g. its offset in a slice
+	#[inline]
+	pub fn offset(&self) -> usize {
+		self.regions
+			.iter()
+			.find(|region| region.get(self.address, 1).is_some())
+			.map(|region| self.address - region.address)
+			.unwrap_or_default()
+	}
+
+	/// Moves to another address in the same memory, e.g. the address of a [`Capture`](crate::Capture)
+	pub fn at(mut self, address: usize) -> Result<Self, ResolveError> {
+		if !self.regions.iter().any(|region| region.get(address, 1).is_some()) {
+			return Err(ResolveError::OutOfBounds { address, len: 0 });
+		}
+		self.address = address;
+		Ok(self)
+	}
+
+	/// Moves `n` bytes forward
+	#[inline]
+	#[allow(clippy::should_implement_trait)]
+	pub fn add(self, n: usize) -> Result<Self, ResolveError> {
+		self.offset_by(n as i128)
+	}
+
+	/// Moves `n` bytes back
+	#[inline]
+	#[allow(clippy::should_implement_trait)]
+	pub fn sub(self, n: usize) -> Result<Self, ResolveError> {
+		self.offset_by(-(n as i128))
+	}
+
+	fn offset_by(self, offset: i128) -> Result<Self, ResolveError> {
+		let address = self.address;
+		match usize::try_from(address as i128 + offset) {
+			Ok(target) => self.at(target),
+			Err(_) => Err(ResolveError::Overflow { address, offset }),
+		}
+	}
+
+	/// Follows a 32-bit displacement, relative to the end of the instruction, e.g. of a `call`, `jmp` or `lea reg, [rip+disp32]`
+	///
+	/// The address must be at the start of the instruction, `offset` is where the displacement is in the instruction and
+	/// `insn_len` is the length of the instruction, e.g. `rel32(1, 5)` for `E8 rel32` or `rel32(3, 7)` for `48 8D 05 disp32`
+	pub fn rel32(self, offset: usize, insn_len: usize) -> Result<Self, ResolveError> {
+		let displacement = self.clone().add(offset)?.deref::<i32>()?;
+		self.offset_by(insn_len as i128 + displacement as i128)
+	}
+
+	/// Follows an 8-bit displacement, relative to the end of the instruction, e.g. of a short `jmp` or `jcc`
+	///
+	/// See [`Address::rel32`], e.g. `rel8(1, 2)` for `EB rel8`
+	pub fn rel8(self, offset: usize, insn_len: usize) -> Result<Self, ResolveError> {
+		let displacement = self.clone().add(offset)?.deref::<i8>()?;
+		self.offset_by(insn_len as i128 + displacement as i128)
+	}
+
+	/// Reads a `T` at the address
+	pub fn deref<T: Pod>(&self) -> Result<T, ResolveError> {
+		let bytes = self.bytes(std::mem::size_of::<T>())?;
+		// Safety: the bytes are in bounds and `Pod` types can be read from any bytes
+		Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+	}
+
+	/// Reads a pointer at the address and moves to where it points, which must be in the same memory
+	#[inline]
+	pub fn deref_ptr(self) -> Result<Self, ResolveError> {
+		let target = self.deref::<usize>()?;
+		self.at(target)
+	}
+
+	/// Returns the `len` bytes at the address
+	pub fn bytes(&self, len: usize) -> Result<&'a [u8], ResolveError> {
+		self.regions
+			.iter()
+			.find_map(|region| region.get(self.address, len))
+			.ok_or(ResolveError::OutOfBounds { address: self.address, len })
+	}
+}
+impl std::fmt::Debug for Address<'_> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Address({:#x})", self.address)
+	}
+}
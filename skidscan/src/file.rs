@@ -0,0 +1,171 @@
+use crate::stream::MAX_OVERLAP;
+use crate::{ParallelScanner, Signature, StreamScanner};
+
+use std::fs::File;
+use std::convert::TryFrom;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Scans a file for signatures without reading all of it into memory, e.g. a raw memory dump or minidump
+///
+/// By default the file is read and scanned in chunks, which overlap by at most 1 MiB, the same as a [`StreamScanner`]. Memory mapping
+/// it instead is opt-in with [`FileScanner::mmap`], since it's only sound if nothing modifies the file while it's scanned, and is only
+/// done on Linux. Every result is an offset in the file.
+///
+/// ```ignore
+/// let offsets: Vec<u64> = FileScanner::open("game.dmp")?.scan_all(&signature!("E8 ? ? ? ? 48 85 C0"))?;
+/// ```
+#[derive(Debug)]
+pub struct FileScanner {
+	file: File,
+	chunk_size: usize,
+	mmap: bool,
+}
+impl FileScanner {
+	/// Scans a file that is already open, from its start
+	///
+	/// The file is read at offsets without moving its cursor, so it can be scanned from multiple threads or used elsewhere at the same time.
+	#[inline]
+	pub fn new(file: File) -> FileScanner {
+		FileScanner {
+			file,
+			chunk_size: 1 << 20,
+			mmap: false,
+		}
+	}
+
+	/// Opens a file to scan
+	#[inline]
+	pub fn open<P: AsRef<Path>>(path: P) -> io::Result<FileScanner> {
+		File::open(path).map(FileScanner::new)
+	}
+
+	/// Sets how many bytes are read at a time when scanning, 1 MiB by default
+	#[inline]
+	pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+		self.chunk_size = chunk_size.max(1);
+		self
+	}
+
+	/// Memory maps the whole file when scanning instead of reading it in chunks, which is disabled by default
+	///
+	/// If the file can't be mapped, e.g. because it's larger than the address space or isn't a regular file, it's read in chunks instead.
+	/// Files are only mapped on Linux, elsewhere they are always read in chunks.
+	///
+	/// # Safety
+	///
+	/// The file must not be truncated or modified while it's scanned, which can crash the process or change the mapped data.
+	#[inline]
+	pub unsafe fn mmap(mut self, mmap: bool) -> Self {
+		self.mmap = mmap;
+		self
+	}
+
+	/// Scans the file, returning the offset of every occurrence of the signature in ascending order
+	pub fn scan_all(&self, signature: &Signature) -> io::Result<Vec<u64>> {
+		#[cfg(target_os = "linux")]
+		if self.mmap {
+			if let Some(mapping) = self.map()? {
+				return Ok(signature.scan_all(mapping.as_slice()).map(|offset| offset as u64).collect());
+			}
+		}
+		let reader = ShardReader {
+			file: &self.file,
+			offset: 0,
+			end: u64::MAX,
+		};
+		StreamScanner::new(signature).scan_reader_chunks(reader, self.chunk_size)
+	}
+
+	/// Scans the file in shards on multiple threads, each read in chunks
+	pub(crate) fn par_scan_all(&self, signature: &Signature, parallel: &ParallelScanner) -> io::Result<Vec<u64>> {
+		#[cfg(target_os = "linux")]
+		if self.mmap {
+			if let Some(mapping) = self.map()? {
+				return Ok(parallel.scan_all(signature, mapping.as_slice()).into_iter().map(|offset| offset as u64).collect());
+			}
+		}
+
+		let len = self.file.metadata()?.len();
+		let shard_size = parallel.shard_size as u64;
+		// Shards overlap as much as chunks do, so they find the same matches as a serial scan without reading more of the file
+		let overlap = signature.max_len().saturating_sub(1).min(MAX_OVERLAP) as u64;
+		let shards = usize::try_from((len / shard_size + u64::from(len % shard_size != 0)).max(1)).unwrap_or(usize::MAX);
+		let found = parallel.run(shards, |shard| {
+			let start = shard as u64 * shard_size;
+			let end = start.saturating_add(shard_size).min(len);
+			let reader = ShardReader {
+				file: &self.file,
+				offset: start,
+				end: end.saturating_add(overlap).min(len),
+			};
+			let found = StreamScanner::new(signature).scan_reader_chunks(reader, self.chunk_size)?;
+			Ok(found.into_iter().take_while(|offset| *offset < end - start).map(|offset| start + offset).collect())
+		});
+		found.into_iter().collect::<io::Result<Vec<Vec<u64>>>>().map(|found| found.concat())
+	}
+
+	/// Maps the file into memory, or returns `None` if it can't be mapped
+	#[cfg(target_os = "linux")]
+	fn map(&self) -> io::Result<Option<Mapping>> {
+		use std::os::unix::io::AsRawFd;
+
+		let len = match usize::try_from(self.file.metadata()?.len()) {
+			Ok(0) => return Ok(Some(Mapping { ptr: std::ptr::null_mut(), len: 0 })),
+			Ok(len) => len,
+			Err(_) => return Ok(None),
+		};
+		let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, self.file.as_raw_fd(), 0) };
+		if ptr == libc::MAP_FAILED {
+			return Ok(None);
+		}
+		unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+		Ok(Some(Mapping { ptr, len }))
+	}
+}
+
+/// Reads part of a file, up to `end` or the end of the file, without moving its cursor so that other parts can be read at the same time
+struct ShardReader<'a> {
+	file: &'a File,
+	offset: u64,
+	end: u64,
+}
+impl Read for ShardReader<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let len = buf.len().min(usize::try_from(self.end.saturating_sub(self.offset)).unwrap_or(usize::MAX));
+		if len == 0 {
+			return Ok(0);
+		}
+		#[cfg(unix)]
+		let read = std::os::unix::fs::FileExt::read_at(self.file, &mut buf[..len], self.offset)?;
+		#[cfg(windows)]
+		let read = std::os::windows::fs::FileExt::seek_read(self.file, &mut buf[..len], self.offset)?;
+		self.offset += read as u64;
+		Ok(read)
+	}
+}
+
+/// Unmaps a mapped file when dropped
+#[cfg(target_os = "linux")]
+struct Mapping {
+	ptr: *mut std::ffi::c_void,
+	len: usize,
+}
+#[cfg(target_os = "linux")]
+impl Mapping {
+	fn as_slice(&self) -> &[u8] {
+		if self.len == 0 {
+			&[]
+		} else {
+			unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+		}
+	}
+}
+#[cfg(target_os = "linux")]
+impl Drop for Mapping {
+	fn drop(&mut self) {
+		if self.len != 0 {
+			unsafe { libc::munmap(self.ptr, self.len) };
+		}
+	}
+}
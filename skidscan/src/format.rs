@@ -0,0 +1,275 @@
+use crate::{SigByte, Signature, SignatureParseError};
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// The textual formats a [`Signature`] can be parsed from and formatted as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SignatureFormat {
+	/// IDA style, space separated with `?` wildcards, e.g. `55 8B EC ? 4? 00`
+	///
+	/// `??` wildcards are accepted when parsing. This is the format of [`FromStr`] and the `signature!` macro,
+	/// and the only format that can represent arbitrary masks (e.g. `40&F8`), gaps (e.g. `[2-6]`), alternatives (e.g. `(74|75)`) and captures (e.g. `${'rel:4}`).
+	Ida,
+
+	/// Compact IDA style without any spaces and with `??` wildcards, e.g. `558BEC??4?00`
+	IdaCompact,
+
+	/// Code style, an escaped byte string followed by a mask of `x` (fixed) and `?` (wildcard), e.g. `\x55\x8B\xEC\x00\x00 xxx?x`
+	///
+	/// When parsing, the byte string and mask may be quoted and separated by whitespace or a comma, e.g. `"\x55\x8B\xEC\x00", "xxx?"`.
+	/// Wildcard bytes are formatted as `\x00`.
+	Code,
+
+	/// x64dbg style, space separated with `??` wildcards, e.g. `55 8B EC ?? 4? 00`
+	X64dbg,
+
+	/// A comma separated array of hex bytes, e.g. `0x55, 0x8B, 0xEC`
+	///
+	/// When parsing, the array may be surrounded by braces or brackets. Wildcards can't be represented in this format.
+	Array,
+}
+
+impl Signature {
+	/// Parses a signature in the given format
+	pub fn parse_as(format: SignatureFormat, s: &str) -> Result<Signature, SignatureParseError> {
+		match format {
+			SignatureFormat::Ida | SignatureFormat::X64dbg => Signature::from_str(s),
+			SignatureFormat::IdaCompact => parse_compact(s),
+			SignatureFormat::Code => parse_code(s),
+			SignatureFormat::Array => parse_array(s),
+		}
+	}
+
+	/// Parses a code style signature from its escaped byte string (e.g. `\x55\x8B\xEC\x00`) and mask (e.g. `xxx?`)
+	pub fn from_code(bytes: &str, mask: &str) -> Result<Signature, SignatureParseError> {
+		apply_mask(mask, parse_escaped(bytes, bytes)?, mask)
+	}
+
+	/// Formats this signature in the given format
+	///
+	/// Returns `None` if the signature can't be represented in the format, e.g. wildcards in [`SignatureFormat::Array`], half wildcards in [`SignatureFormat::Code`]
+	/// or gaps, alternatives and captures in anything but [`SignatureFormat::Ida`]
+	pub fn to_string_as(&self, format: SignatureFormat) -> Option<String> {
+		let mut s = String::with_capacity(self.len() * 4);
+		match format {
+			SignatureFormat::Ida | SignatureFormat::X64dbg => {
+				for (i, element) in self.iter().enumerate() {
+					if i != 0 {
+						s.push(' ');
+					}
+					let byte = match element.as_byte() {
+						Some(byte) => byte,
+						// Gaps, alternatives and captures are an extension of the IDA style
+						None if format == SignatureFormat::Ida => {
+							write!(s, "{}", element).ok()?;
+							continue;
+						}
+						None => return None,
+					};
+					match byte.mask() {
+						0x00 if format == SignatureFormat::Ida => s.push('?'),
+						0x00 | 0xFF | 0xF0 | 0x0F => write!(s, "{}", byte).ok()?,
+						// Arbitrary masks are an extension of the IDA style
+						_ if format == SignatureFormat::Ida => write!(s, "{}", byte).ok()?,
+						_ => return None,
+					}
+				}
+			}
+			SignatureFormat::IdaCompact => {
+				for byte in self.iter() {
+					match byte.as_byte()?.mask() {
+						0x00 | 0xFF | 0xF0 | 0x0F => write!(s, "{}", byte).ok()?,
+						_ => return None,
+					}
+				}
+			}
+			SignatureFormat::Code => {
+				let mut mask = String::with_capacity(self.len());
+				for byte in self.iter() {
+					let byte = byte.as_byte()?;
+					match byte.mask() {
+						0x00 => mask.push('?'),
+						0xFF => mask.push('x'),
+						_ => return None,
+					}
+					write!(s, "\\x{:02X}", byte.value()).ok()?;
+				}
+				s.push(' ');
+				s.push_str(&mask);
+			}
+			SignatureFormat::Array => {
+				for (i, byte) in self.iter().enumerate() {
+					if i != 0 {
+						s.push_str(", ");
+					}
+					write!(s, "0x{:02X}", byte.as_byte()?.as_exact()?).ok()?;
+				}
+			}
+		}
+		Some(s)
+	}
+}
+
+/// The byte offset of `token` within `s`, which it must be a subslice of
+#[inline]
+fn offset_in(s: &str, token: &str) -> usize {
+	token.as_ptr() as usize - s.as_ptr() as usize
+}
+
+fn finish(mut signature: Signature) -> Result<Signature, SignatureParseError> {
+	if signature.is_empty() {
+		Err(SignatureParseError::Empty)
+	} else if signature.iter().all(|byte| byte.is_any()) {
+		Err(SignatureParseError::OnlyAny)
+	} else {
+		signature.shrink_to_fit();
+		Ok(signature)
+	}
+}
+
+fn parse_hex(s: &str, token: &str) -> Result<u8, SignatureParseError> {
+	if !token.is_empty() && token.len() <= 2 && token.bytes().all(|c| c.is_ascii_hexdigit()) {
+		if let Ok(byte) = u8::from_str_radix(token, 16) {
+			return Ok(byte);
+		}
+	}
+	Err(SignatureParseError::InvalidByte {
+		position: offset_in(s, token),
+		token: token.to_string(),
+	})
+}
+
+fn parse_compact(s: &str) -> Result<Signature, SignatureParseError> {
+	let mut signature = Signature::with_capacity(s.len() / 2);
+	for word in s.split_whitespace() {
+		let mut i = 0;
+		while i < word.len() {
+			let end = word[i..].char_indices().nth(2).map(|(len, _)| i + len).unwrap_or(word.len());
+			let token = &word[i..end];
+			match token.parse::<SigByte>() {
+				Ok(byte) if token.len() == 2 => signature.push(byte.into()),
+				_ => {
+					return Err(SignatureParseError::InvalidByte {
+						position: offset_in(s, token),
+						token: token.to_string(),
+					})
+				}
+			}
+			i = end;
+		}
+	}
+	finish(signature)
+}
+
+/// Parses a string of `\xHH` escapes
+fn parse_escaped(s: &str, escaped: &str) -> Result<Vec<u8>, SignatureParseError> {
+	let mut bytes = Vec::with_capacity(escaped.len() / 4);
+	let mut rest = escaped;
+	while !rest.is_empty() {
+		let hex = rest
+			.strip_prefix("\\x")
+			.or_else(|| rest.strip_prefix("\\X"))
+			.filter(|hex| hex.len() >= 2 && hex.is_char_boundary(2))
+			.map(|hex| &hex[..2]);
+		match hex {
+			Some(hex) => bytes.push(parse_hex(s, hex)?),
+			None => {
+				let token = &rest[..rest.char_indices().nth(4).map(|(len, _)| len).unwrap_or(rest.len())];
+				return Err(SignatureParseError::InvalidByte {
+					position: offset_in(s, token),
+					token: token.to_string(),
+				});
+			}
+		}
+		rest = &rest[4..];
+	}
+	Ok(bytes)
+}
+
+fn parse_code(s: &str) -> Result<Signature, SignatureParseError> {
+	let mut parts = s
+		.split(|c: char| c.is_whitespace() || c == ',')
+		.filter(|part| !part.is_empty())
+		.map(|part| part.trim_matches('"'));
+	let (escaped, mask) = match (parts.next(), parts.next()) {
+		(Some(escaped), Some(mask)) => (escaped, mask),
+		(Some(escaped), None) => {
+			return Err(SignatureParseError::MaskLength {
+				bytes: parse_escaped(s, escaped)?.len(),
+				mask: 0,
+			})
+		}
+		_ => return Err(SignatureParseError::Empty),
+	};
+	if let Some(extra) = parts.next() {
+		return Err(SignatureParseError::InvalidByte {
+			position: offset_in(s, extra),
+			token: extra.to_string(),
+		});
+	}
+
+	apply_mask(s, parse_escaped(s, escaped)?, mask)
+}
+
+/// Builds a signature from the bytes of a code style signature and its mask
+fn apply_mask(s: &str, pattern: Vec<u8>, mask: &str) -> Result<Signature, SignatureParseError> {
+	if pattern.len() != mask.len() {
+		return Err(SignatureParseError::MaskLength {
+			bytes: pattern.len(),
+			mask: mask.len(),
+		});
+	}
+
+	let mut signature = Signature::with_capacity(pattern.len());
+	for (i, (byte, mask_char)) in pattern.into_iter().zip(mask.bytes()).enumerate() {
+		match mask_char {
+			b'x' | b'X' => signature.push_byte(byte),
+			b'?' => signature.push_any(),
+			_ => {
+				return Err(SignatureParseError::InvalidByte {
+					position: offset_in(s, &mask[i..]),
+					token: mask[i..].chars().next().map(String::from).unwrap_or_default(),
+				})
+			}
+		}
+	}
+	finish(signature)
+}
+
+fn parse_array(s: &str) -> Result<Signature, SignatureParseError> {
+	let trimmed = s.trim();
+	let inner = trimmed
+		.strip_prefix('{')
+		.and_then(|inner| inner.strip_suffix('}'))
+		.or_else(|| trimmed.strip_prefix('[').and_then(|inner| inner.strip_suffix(']')))
+		.unwrap_or(trimmed);
+
+	if inner.trim().is_empty() {
+		return Err(SignatureParseError::Empty);
+	}
+
+	let tokens: Vec<&str> = inner.split(',').map(str::trim).collect();
+	let mut signature = Signature::with_capacity(tokens.len());
+	for (i, token) in tokens.iter().copied().enumerate() {
+		if token.is_empty() {
+			// Allow a trailing comma
+			if i != 0 && i == tokens.len() - 1 {
+				continue;
+			}
+			return Err(SignatureParseError::InvalidByte {
+				position: offset_in(s, token),
+				token: String::new(),
+			});
+		}
+		let hex = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")).ok_or_else(|| SignatureParseError::InvalidByte {
+			position: offset_in(s, token),
+			token: token.to_string(),
+		})?;
+		signature.push_byte(parse_hex(s, hex).map_err(|_| SignatureParseError::InvalidByte {
+			position: offset_in(s, token),
+			token: token.to_string(),
+		})?);
+	}
+	finish(signature)
+}
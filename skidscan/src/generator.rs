@@ -0,0 +1,192 @@
+use crate::x86::{self, Arch, OperandKind};
+use crate::{Module, SigByte, SigElement, Signature};
+
+use std::ops::Range;
+
+/// An error generating a signature
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GenerateError {
+	/// The target isn't in the memory that is scanned
+	OutOfBounds { target: usize },
+
+	/// The instruction at this offset (or address, for modules) couldn't be decoded
+	InvalidInstruction { at: usize },
+
+	/// No signature of up to `len` bytes is unique, it still matches this many places
+	NotUnique { len: usize, matches: usize },
+}
+impl std::fmt::Display for GenerateError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			GenerateError::OutOfBounds { target } => write!(f, "target {:#x} is outside of the scanned memory", target),
+			GenerateError::InvalidInstruction { at } => write!(f, "invalid instruction at {:#x}", at),
+			GenerateError::NotUnique { len, matches } => write!(f, "no unique signature of up to {} bytes, still found {} times", len, matches),
+		}
+	}
+}
+impl std::error::Error for GenerateError {}
+
+/// Generates the shortest unique signature for the code at a target, wildcarding operands that change when the code is relocated or relinked
+///
+/// Instructions are decoded from the target onwards, and their bytes are added to the signature until it only matches the target.
+/// Relative displacements (`call rel32`, `jmp rel32`, `jcc rel32`), RIP-relative and absolute memory operands and immediates in the
+/// [address range](SignatureGenerator::address_range) are wildcarded, short `rel8` jumps and other operands are kept.
+///
+/// The signature can be formatted with [`SignatureFormat::Ida`](crate::SignatureFormat::Ida) and pasted into `signature!`.
+///
+/// ```ignore
+/// let libc = Module::resolve("libc.so.6")?;
+/// let signature = unsafe { SignatureGenerator::new().generate_module(&libc, function as usize) }?;
+/// println!("signature!(\"{}\")", signature.to_string_as(SignatureFormat::Ida).unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignatureGenerator {
+	arch: Arch,
+	max_len: usize,
+	address_range: Option<Range<u64>>,
+}
+impl Default for SignatureGenerator {
+	#[inline]
+	fn default() -> Self {
+		SignatureGenerator {
+			arch: Arch::default(),
+			max_len: 64,
+			address_range: None,
+		}
+	}
+}
+impl SignatureGenerator {
+	/// Creates a generator for the instruction set of this process, generating signatures of up to 64 bytes
+	#[inline]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sets the instruction set of the code
+	#[inline]
+	pub fn arch(mut self, arch: Arch) -> Self {
+		self.arch = arch;
+		self
+	}
+
+	/// Sets the longest signature to generate, in bytes
+	#[inline]
+	pub fn max_len(mut self, max_len: usize) -> Self {
+		self.max_len = max_len;
+		self
+	}
+
+	/// Sets the addresses the code can refer to, immediates in this range are treated as addresses and wildcarded
+	///
+	/// For modules this defaults to the address range of the module.
+	#[inline]
+	pub fn address_range(mut self, range: Range<u64>) -> Self {
+		self.address_range = Some(range);
+		self
+	}
+
+	/// Generates the shortest signature for the code at `offset` that is unique in `bytes`
+	#[inline]
+	pub fn generate(&self, bytes: &[u8], offset: usize) -> Result<Signature, GenerateError> {
+		self.generate_in(&[(0, bytes)], 0, offset, self.address_range.clone())
+	}
+
+	/// Generates the shortest signature for the code at `address` that is unique in the readable segments of the module
+	///
+	/// The module must still be loaded
+	pub unsafe fn generate_module(&self, module: &Module, address: usize) -> Result<Signature, GenerateError> {
+		let regions: Vec<(usize, &[u8])> = module.readable_segments(None).map(|segment| (segment.address, segment.as_slice())).collect();
+		let (region, (start, _)) = regions
+			.iter()
+			.enumerate()
+			.find(|(_, (start, bytes))| address >= *start && address - start < bytes.len())
+			.ok_or(GenerateError::OutOfBounds { target: address })?;
+
+		let address_range = self.address_range.clone().or_else(|| {
+			let start = regions.iter().map(|(start, _)| *start).min()?;
+			let end = regions.iter().map(|(start, bytes)| start + bytes.len()).max()?;
+			Some(start as u64..end as u64)
+		});
+		self.generate_in(&regions, region, address - start, address_range)
+	}
+
+	/// Generates a signature for the code at `offset` in one of the regions that is unique in all of them
+	///
+	/// Regions are paired with their address, which errors are reported relative to.
+	fn generate_in(&self, regions: &[(usize, &[u8])], region: usize, offset: usize, address_range: Option<Range<u64>>) -> Result<Signature, GenerateError> {
+		let (base, bytes) = regions[region];
+		if offset >= bytes.len() {
+			return Err(GenerateError::OutOfBounds { target: base + offset });
+		}
+
+		let mut signature = Signature::default();
+		// Where the signature still matches, as (region, offset)
+		let mut candidates: Option<Vec<(usize, usize)>> = None;
+		let mut pos = offset;
+		while pos < bytes.len() && signature.len() < self.max_len {
+			let instruction = x86::decode(&bytes[pos..], self.arch).ok_or(GenerateError::InvalidInstruction { at: base + pos })?;
+			let code = &bytes[pos..pos + instruction.len];
+			let mut wildcard = [false; 15];
+			for operand in instruction.displacement.iter().chain(instruction.immediate.iter()) {
+				let relocated = match operand.kind {
+					OperandKind::Relative => operand.len >= 4,
+					OperandKind::RipRelative | OperandKind::Absolute => true,
+					OperandKind::Immediate => operand.len >= 4 && address_range.as_ref().map(|range| range.contains(&operand.value(code))).unwrap_or(false),
+					OperandKind::Displacement => false,
+				};
+				if relocated {
+					wildcard[operand.offset..operand.offset + operand.len].iter_mut().for_each(|wildcard| *wildcard = true);
+				}
+			}
+
+			for (i, byte) in code.iter().enumerate() {
+				if signature.len() == self.max_len {
+					break;
+				}
+				if wildcard[i] {
+					signature.push_any();
+					continue;
+				}
+				signature.push_byte(*byte);
+
+				// Finding every match of a single instruction is enough to narrow it down byte by byte from then on
+				let index = signature.len() - 1;
+				if let Some(candidates) = &mut candidates {
+					candidates.retain(|(region, offset)| regions[*region].1.get(offset + index).map(|byte| *byte == code[i]).unwrap_or(false));
+					if candidates.len() == 1 {
+						return Ok(signature);
+					}
+				} else if i + 1 == code.len() && candidates.get_or_insert(scan_regions(&signature, regions)).len() == 1 {
+					return Ok(shortest_prefix(signature, regions));
+				}
+			}
+			pos += instruction.len;
+		}
+
+		let matches = match candidates {
+			Some(candidates) => candidates.len(),
+			None => scan_regions(&signature, regions).len(),
+		};
+		Err(GenerateError::NotUnique { len: signature.len(), matches })
+	}
+}
+
+/// Finds every match of a signature in the regions
+fn scan_regions(signature: &Signature, regions: &[(usize, &[u8])]) -> Vec<(usize, usize)> {
+	regions
+		.iter()
+		.enumerate()
+		.flat_map(|(region, (_, bytes))| signature.scan_all(bytes).map(move |offset| (region, offset)))
+		.collect()
+}
+
+/// Shortens a unique signature that was only checked after its first instruction to its shortest unique prefix
+fn shortest_prefix(signature: Signature, regions: &[(usize, &[u8])]) -> Signature {
+	(1..signature.len())
+		.map(|len| Signature::from(&signature[..len]))
+		.find(|prefix| {
+			matches!(prefix.last(), Some(SigElement::Byte(byte)) if *byte != SigByte::ANY)
+				&& regions.iter().flat_map(|(_, bytes)| prefix.scan_all(bytes)).take(2).count() == 1
+		})
+		.unwrap_or(signature)
+}
@@ -0,0 +1,364 @@
+use super::{Image, ImageError, ImageFormat, ImageSection, Reader};
+use crate::SegmentFlags;
+
+pub(super) const MAGIC: &[u8] = b"\x7FELF";
+
+const PT_LOAD: u32 = 1;
+const SHT_NOBITS: u32 = 8;
+const SHN_XINDEX: u16 = 0xFFFF;
+const PN_XNUM: u16 = 0xFFFF;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+fn malformed(reason: &'static str) -> ImageError {
+	ImageError::Malformed { reason }
+}
+
+pub(super) fn parse(data: &[u8]) -> Result<Image<'_>, ImageError> {
+	if !data.starts_with(MAGIC) {
+		return Err(ImageError::UnknownFormat);
+	}
+	let wide = match data.get(4) {
+		Some(1) => false,
+		Some(2) => true,
+		_ => return Err(malformed("invalid ELF class")),
+	};
+	let file = Reader::new(
+		data,
+		match data.get(5) {
+			Some(1) => false,
+			Some(2) => true,
+			_ => return Err(malformed("invalid ELF data encoding")),
+		},
+	);
+
+	// The ELF header is the same for both classes up to e_entry, after which the addresses and offsets are 8 bytes in ELF64
+	let w = if wide { 8 } else { 4 };
+	let phoff = file.word(24 + w, wide)?;
+	let shoff = file.word(24 + 2 * w, wide)?;
+	let counts = 24 + 3 * w + 4 + 2;
+	let phentsize = file.u16(counts)? as u64;
+	let mut phnum = file.u16(counts + 2)? as u64;
+	let shentsize = file.u16(counts + 4)? as u64;
+	let mut shnum = file.u16(counts + 6)? as u64;
+	let mut shstrndx = file.u16(counts + 8)?;
+
+	let section_header = |index: u64| -> Result<SectionHeader, ImageError> {
+		let header = shoff
+			.checked_add(index.checked_mul(shentsize).ok_or_else(|| malformed("too many section headers"))?)
+			.ok_or_else(|| malformed("section header is outside of the file"))?;
+		SectionHeader::read(&file, header, wide)
+	};
+
+	// Counts that don't fit in the ELF header are in the first section header
+	if shoff != 0 && (shnum == 0 || shstrndx == SHN_XINDEX || phnum == PN_XNUM as u64) {
+		let first = section_header(0)?;
+		if shnum == 0 {
+			shnum = first.size;
+		}
+		if shstrndx == SHN_XINDEX {
+			shstrndx = first.link as u16;
+		}
+		if phnum == PN_XNUM as u64 {
+			phnum = first.info as u64;
+		}
+	}
+
+	let mut segments = Vec::new();
+	for index in 0..phnum {
+		let header = phoff
+			.checked_add(index.checked_mul(phentsize).ok_or_else(|| malformed("too many program headers"))?)
+			.ok_or_else(|| malformed("program header is outside of the file"))?;
+		if file.u32(header)? != PT_LOAD {
+			continue;
+		}
+		let (p_flags, p_offset, p_vaddr, p_filesz, p_memsz) = if wide {
+			(file.u32(header + 4)?, file.u64(header + 8)?, file.u64(header + 16)?, file.u64(header + 32)?, file.u64(header + 40)?)
+		} else {
+			(
+				file.u32(header + 24)?,
+				file.u32(header + 4)? as u64,
+				file.u32(header + 8)? as u64,
+				file.u32(header + 16)? as u64,
+				file.u32(header + 20)? as u64,
+			)
+		};
+
+		let mut flags = SegmentFlags::NONE;
+		for (flag, bit) in [(SegmentFlags::R, PF_R), (SegmentFlags::W, PF_W), (SegmentFlags::X, PF_X)].iter() {
+			if p_flags & bit != 0 {
+				flags = flags | *flag;
+			}
+		}
+		let segment = ImageSection {
+			name: format!("LOAD{}", index),
+			address: p_vaddr,
+			size: p_memsz,
+			offset: p_offset,
+			file_size: p_filesz.min(p_memsz),
+			flags,
+		};
+		Image::check_section(data, &segment)?;
+		segments.push(segment);
+	}
+
+	let mut headers = Vec::new();
+	if shoff != 0 {
+		for index in 0..shnum {
+			headers.push(section_header(index)?);
+		}
+	}
+	let names = match headers.get(shstrndx as usize) {
+		Some(strtab) => file.bytes(strtab.offset, strtab.size)?,
+		None => &[],
+	};
+
+	let mut sections = Vec::new();
+	for header in headers.iter().skip(1) {
+		let name = names.get(header.name as usize..).unwrap_or_default();
+		let name = &name[..name.iter().position(|byte| *byte == 0).unwrap_or(name.len())];
+
+		let mut flags = SegmentFlags::NONE;
+		if header.flags & SHF_ALLOC != 0 {
+			flags = flags | SegmentFlags::R;
+			if header.flags & SHF_WRITE != 0 {
+				flags = flags | SegmentFlags::W;
+			}
+			if header.flags & SHF_EXECINSTR != 0 {
+				flags = flags | SegmentFlags::X;
+			}
+		}
+		let section = ImageSection {
+			name: String::from_utf8_lossy(name).into_owned(),
+			address: header.addr,
+			size: header.size,
+			offset: header.offset,
+			file_size: if header.kind == SHT_NOBITS { 0 } else { header.size },
+			flags,
+		};
+		Image::check_section(data, &section)?;
+		sections.push(section);
+	}
+
+	let base = segments.iter().map(|segment| segment.address & !0xFFF).min().unwrap_or(0);
+	Image::new(data, ImageFormat::Elf, base, None, sections, segments)
+}
+
+struct SectionHeader {
+	name: u32,
+	kind: u32,
+	flags: u64,
+	addr: u64,
+	offset: u64,
+	size: u64,
+	link: u32,
+	info: u32,
+}
+impl SectionHeader {
+	fn read(file: &Reader, header: u64, wide: bool) -> Result<Self, ImageError> {
+		let w = if wide { 8 } else { 4 };
+		Ok(SectionHeader {
+			name: file.u32(header)?,
+			kind: file.u32(header + 4)?,
+			flags: file.word(header + 8, wide)?,
+			addr: file.word(header + 8 + w, wide)?,
+			offset: file.word(header + 8 + 2 * w, wide)?,
+			size: file.word(header + 8 + 3 * w, wide)?,
+			link: file.u32(header + 8 + 4 * w)?,
+			info: file.u32(header + 12 + 4 * w)?,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{Image, ImageError, ImageFormat, ImageMatch, SegmentFlags, Signature};
+
+	/// Writes integers of either endianness
+	struct Writer {
+		file: Vec<u8>,
+		big_endian: bool,
+	}
+	impl Writer {
+		fn put(&mut self, value: u64, len: usize) {
+			let bytes = value.to_le_bytes();
+			if self.big_endian {
+				self.file.extend(bytes[..len].iter().rev());
+			} else {
+				self.file.extend(&bytes[..len]);
+			}
+		}
+	}
+
+	const TEXT: &[u8] = &[0x55, 0x89, 0xE5, 0xE8, 0x10, 0x00, 0x00, 0x00, 0x5D, 0xC3, 0x55, 0x89, 0xE5];
+	const NAMES: &[u8] = b"\0.text\0.bss\0.shstrtab\0";
+
+	/// Builds an ELF with a `PT_LOAD` segment at 0x10000 made up of its headers, `.text` and `.shstrtab`, and 16 bytes of `.bss`
+	fn build(wide: bool, big_endian: bool) -> (Vec<u8>, u64) {
+		let w = if wide { 8 } else { 4 };
+		let (ehsize, phentsize, shentsize) = if wide { (64, 56, 64) } else { (52, 32, 40) };
+		let text = ehsize + phentsize;
+		let names = text + TEXT.len() as u64;
+		let shoff = names + NAMES.len() as u64;
+
+		let mut elf = Writer { file: Vec::new(), big_endian };
+		elf.file.extend(b"\x7FELF");
+		elf.file.extend(&[if wide { 2 } else { 1 }, if big_endian { 2 } else { 1 }, 1]);
+		elf.file.extend(&[0; 9]);
+		elf.put(2, 2);
+		elf.put(if wide { 62 } else { 3 }, 2);
+		elf.put(1, 4);
+		elf.put(0x10000 + text, w);
+		elf.put(ehsize, w);
+		elf.put(shoff, w);
+		elf.put(0, 4);
+		for field in [ehsize, phentsize, 1, shentsize, 4, 3].iter() {
+			elf.put(*field, 2);
+		}
+
+		// PT_LOAD, R|X
+		let (offset, address, file_size, size) = (0, 0x10000, names + NAMES.len() as u64, names + NAMES.len() as u64 + 16);
+		if wide {
+			elf.put(1, 4);
+			elf.put(5, 4);
+			for field in [offset, address, address, file_size, size, 0x1000].iter() {
+				elf.put(*field, 8);
+			}
+		} else {
+			for field in [1, offset, address, address, file_size, size, 5, 0x1000].iter() {
+				elf.put(*field, 4);
+			}
+		}
+
+		elf.file.extend(TEXT);
+		elf.file.extend(NAMES);
+
+		// Null, .text (ALLOC|EXECINSTR), .bss (WRITE|ALLOC, NOBITS), .shstrtab
+		let sections = [
+			(0, 0, 0, 0, 0, 0),
+			(1, 1, 6, 0x10000 + text, text, TEXT.len() as u64),
+			(7, 8, 3, 0x10000 + file_size, file_size, 16),
+			(12, 3, 0, 0, names, NAMES.len() as u64),
+		];
+		for (name, kind, flags, address, offset, size) in sections.iter() {
+			elf.put(*name, 4);
+			elf.put(*kind, 4);
+			elf.put(*flags, w);
+			elf.put(*address, w);
+			elf.put(*offset, w);
+			elf.put(*size, w);
+			elf.put(0, 4);
+			elf.put(0, 4);
+			elf.put(1, w);
+			elf.put(0, w);
+		}
+		(elf.file, text)
+	}
+
+	#[test]
+	fn test_parse_elf() {
+		for (wide, big_endian) in [(false, false), (false, true), (true, false), (true, true)].iter() {
+			let (file, text) = build(*wide, *big_endian);
+			let image = Image::parse(&file).unwrap();
+			assert_eq!(image.format(), ImageFormat::Elf);
+			assert_eq!(image.base(), 0x10000);
+			assert_eq!(image.sections().iter().map(|section| section.name.as_str()).collect::<Vec<_>>(), vec![".text", ".bss", ".shstrtab"]);
+			assert_eq!(image.segments().len(), 1);
+			assert_eq!((image.segments()[0].name.as_str(), image.segments()[0].flags), ("LOAD0", SegmentFlags::RX));
+			assert_eq!(image.size(), image.section(".bss").unwrap().address + 16 - 0x10000);
+
+			let section = image.section(".text").unwrap();
+			assert_eq!((section.address, section.offset, section.flags), (0x10000 + text, text, SegmentFlags::RX));
+			assert_eq!(&*image.section_data(section), TEXT);
+			let bss = image.section(".bss").unwrap();
+			assert_eq!((bss.size, bss.file_size, bss.flags), (16, 0, SegmentFlags::RW));
+
+			let signature = Signature::from(&[0xE8, 0x10][..]);
+			let found = ImageMatch {
+				address: 0x10000 + text + 3,
+				rva: Some(text + 3),
+				offset: Some(text + 3),
+			};
+			assert_eq!(image.scan_unique_section(".text", &signature), Ok(found));
+			assert_eq!(image.scan_unique(&signature), Ok(found));
+			assert_eq!(image.scan_segments(&signature, Some(SegmentFlags::RW)), vec![]);
+			assert_eq!(image.scan_unique_segment("LOAD0", &signature), Ok(found));
+			assert_eq!(image.scan_segment("LOAD1", &signature), Err(ImageError::SectionNotFound { name: "LOAD1".to_string() }));
+
+			// .shstrtab isn't loaded, so it has no address relative to the base
+			let names = image.scan_unique_section(".shstrtab", &Signature::from(&b".bss"[..])).unwrap();
+			assert_eq!((names.rva, names.offset), (None, Some(text + TEXT.len() as u64 + 7)));
+
+			let prologue = Signature::from(&[0x55, 0x89, 0xE5][..]);
+			assert_eq!(
+				image.scan_unique_section(".text", &prologue),
+				Err(ImageError::MultipleFound {
+					addresses: vec![0x10000 + text, 0x10000 + text + 10]
+				})
+			);
+			assert_eq!(image.scan_section(".data", &prologue), Err(ImageError::SectionNotFound { name: ".data".to_string() }));
+
+			// .bss is only zeros in memory
+			let zeros = Signature::from(vec![0; 16]);
+			let bss_address = bss.address;
+			assert_eq!(image.scan_section(".bss", &zeros).unwrap()[0].offset, None);
+			assert_eq!(image.scan_all(&zeros).last().map(|found| found.address), Some(bss_address));
+
+			// Matches in the zeros at the end of a segment are found without filling it with zeros first
+			let segment = &image.segments()[0];
+			let memory = image.section_data(segment);
+			for signature in ["00 00 00", "C3 ? 00", "00 [2-4] 00", "00 ? 00", "2E ? ? ? ? [0-40] 00"].iter() {
+				let signature: Signature = signature.parse().unwrap();
+				let expected: Vec<u64> = signature.scan_all(&memory).map(|pos| segment.address + pos as u64).collect();
+				assert_eq!(image.scan_segment("LOAD0", &signature).unwrap().iter().map(|found| found.address).collect::<Vec<_>>(), expected);
+			}
+
+			let memory = image.map();
+			assert_eq!(memory.len() as u64, bss_address + 16 - 0x10000);
+			assert_eq!(&memory[text as usize..text as usize + TEXT.len()], TEXT);
+
+			assert_eq!(Image::parse(&file[..text as usize]), Err(ImageError::Malformed { reason: "section data is outside of the file" }));
+			assert_eq!(Image::parse(&file[..40]), Err(ImageError::Malformed { reason: "truncated" }));
+		}
+		assert_eq!(Image::parse(b"\0ELF"), Err(ImageError::UnknownFormat));
+	}
+
+	#[test]
+	fn test_parse_elf_too_large() {
+		// A segment or .bss that would take up more memory than an image can is rejected instead of being allocated
+		let (mut file, _) = build(true, false);
+		let memsz = 64 + 56 - 16;
+		file[memsz..memsz + 8].copy_from_slice(&(1u64 << 60).to_le_bytes());
+		assert_eq!(Image::parse(&file), Err(ImageError::Malformed { reason: "section is too large" }));
+
+		let (mut file, _) = build(true, false);
+		let bss_size = file.len() - 2 * 64 + 32;
+		file[bss_size..bss_size + 8].copy_from_slice(&(1u64 << 60).to_le_bytes());
+		assert_eq!(Image::parse(&file), Err(ImageError::Malformed { reason: "section is too large" }));
+	}
+
+	#[test]
+	fn test_scan_unique_large_bss() {
+		// A signature that matches every zero of a large .bss stops at the second match instead of collecting all of them
+		let (mut file, _) = build(true, false);
+		let bss = 512u64 << 20;
+		let memsz = 64 + 56 - 16;
+		let filesz = (file.len() - 4 * 64) as u64;
+		file[memsz..memsz + 8].copy_from_slice(&(filesz + bss).to_le_bytes());
+		let bss_size = file.len() - 2 * 64 + 32;
+		file[bss_size..bss_size + 8].copy_from_slice(&bss.to_le_bytes());
+		let image = Image::parse(&file).unwrap();
+
+		let address = image.section(".bss").unwrap().address;
+		let zero = Signature::from(vec![0]);
+		assert_eq!(image.scan_unique_section(".bss", &zero), Err(ImageError::MultipleFound { addresses: vec![address, address + 1] }));
+		assert!(matches!(image.scan_unique_segment("LOAD0", &zero), Err(ImageError::MultipleFound { .. })));
+		assert!(matches!(image.scan_unique(&Signature::from(vec![0; 64])), Err(ImageError::MultipleFound { .. })));
+	}
+}
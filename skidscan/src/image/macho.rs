@@ -0,0 +1,396 @@
+use super::{Image, ImageError, ImageFormat, ImageSection, Reader};
+use crate::SegmentFlags;
+
+const MH_MAGIC: u32 = 0xFEED_FACE;
+const MH_MAGIC_64: u32 = 0xFEED_FACF;
+const MH_CIGAM: u32 = 0xCEFA_EDFE;
+const MH_CIGAM_64: u32 = 0xCFFA_EDFE;
+const FAT_MAGIC: u32 = 0xCAFE_BABE;
+const FAT_MAGIC_64: u32 = 0xCAFE_BABF;
+
+/// Java class files start with `FAT_MAGIC` too, followed by their version, which is at least 45
+const MAX_FAT_ARCHES: u32 = 20;
+
+const LC_SEGMENT: u32 = 0x1;
+const LC_SEGMENT_64: u32 = 0x19;
+
+const SECTION_TYPE: u32 = 0xFF;
+const S_ZEROFILL: u32 = 0x1;
+const S_GB_ZEROFILL: u32 = 0xC;
+const S_THREAD_LOCAL_ZEROFILL: u32 = 0x12;
+
+const VM_PROT_READ: u32 = 0x1;
+const VM_PROT_WRITE: u32 = 0x2;
+const VM_PROT_EXECUTE: u32 = 0x4;
+
+const CPU_ARCH_ABI64: u32 = 0x0100_0000;
+const CPU_TYPE_X86: u32 = 7;
+const CPU_TYPE_ARM: u32 = 12;
+
+/// The CPU type of a Mach-O image, which selects an image from a fat (universal) binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MachOArch {
+	X86,
+	X86_64,
+	Arm,
+	Arm64,
+
+	/// Any other CPU type, e.g. `0x12` for PowerPC
+	Other(u32),
+}
+impl MachOArch {
+	/// The architecture skidscan was compiled for, if it's one that macOS runs on
+	pub fn current() -> Option<MachOArch> {
+		if cfg!(target_arch = "x86") {
+			Some(MachOArch::X86)
+		} else if cfg!(target_arch = "x86_64") {
+			Some(MachOArch::X86_64)
+		} else if cfg!(target_arch = "arm") {
+			Some(MachOArch::Arm)
+		} else if cfg!(target_arch = "aarch64") {
+			Some(MachOArch::Arm64)
+		} else {
+			None
+		}
+	}
+}
+impl From<u32> for MachOArch {
+	fn from(cpu_type: u32) -> Self {
+		match cpu_type {
+			CPU_TYPE_X86 => MachOArch::X86,
+			cpu_type if cpu_type == CPU_TYPE_X86 | CPU_ARCH_ABI64 => MachOArch::X86_64,
+			CPU_TYPE_ARM => MachOArch::Arm,
+			cpu_type if cpu_type == CPU_TYPE_ARM | CPU_ARCH_ABI64 => MachOArch::Arm64,
+			cpu_type => MachOArch::Other(cpu_type),
+		}
+	}
+}
+impl From<MachOArch> for u32 {
+	fn from(arch: MachOArch) -> Self {
+		match arch {
+			MachOArch::X86 => CPU_TYPE_X86,
+			MachOArch::X86_64 => CPU_TYPE_X86 | CPU_ARCH_ABI64,
+			MachOArch::Arm => CPU_TYPE_ARM,
+			MachOArch::Arm64 => CPU_TYPE_ARM | CPU_ARCH_ABI64,
+			MachOArch::Other(cpu_type) => cpu_type,
+		}
+	}
+}
+
+fn malformed(reason: &'static str) -> ImageError {
+	ImageError::Malformed { reason }
+}
+
+pub(super) fn is_macho(data: &[u8]) -> bool {
+	match Reader::new(data, true).u32(0) {
+		Ok(MH_MAGIC | MH_MAGIC_64 | MH_CIGAM | MH_CIGAM_64) => true,
+		Ok(FAT_MAGIC | FAT_MAGIC_64) => matches!(Reader::new(data, true).u32(4), Ok(count) if count < MAX_FAT_ARCHES),
+		_ => false,
+	}
+}
+
+/// Returns the offset, size and architecture of every image in a fat binary, or of the whole file if it's a single image
+pub(super) fn arches(data: &[u8]) -> Result<Vec<(u64, u64, MachOArch)>, ImageError> {
+	let file = Reader::new(data, true);
+	let wide = match file.u32(0)? {
+		FAT_MAGIC => false,
+		FAT_MAGIC_64 => true,
+		MH_MAGIC | MH_MAGIC_64 => return Ok(vec![(0, data.len() as u64, MachOArch::from(file.u32(4)?))]),
+		MH_CIGAM | MH_CIGAM_64 => return Ok(vec![(0, data.len() as u64, MachOArch::from(Reader::new(data, false).u32(4)?))]),
+		_ => return Err(ImageError::UnknownFormat),
+	};
+
+	let count = file.u32(4)? as u64;
+	let mut arches = Vec::new();
+	for index in 0..count {
+		let (offset, size) = if wide {
+			let header = 8 + index * 32;
+			(file.u64(header + 8)?, file.u64(header + 16)?)
+		} else {
+			let header = 8 + index * 20;
+			(file.u32(header + 8)? as u64, file.u32(header + 12)? as u64)
+		};
+		if offset.checked_add(size).map(|end| end > data.len() as u64).unwrap_or(true) {
+			return Err(malformed("fat binary image is outside of the file"));
+		}
+		arches.push((offset, size, MachOArch::from(file.u32(8 + index * if wide { 32 } else { 20 })?)));
+	}
+	Ok(arches)
+}
+
+/// Parses the image of a fat binary for `arch`, or for the current architecture or else the first one if `None`
+pub(super) fn parse(data: &[u8], arch: Option<MachOArch>) -> Result<Image<'_>, ImageError> {
+	let arches = arches(data)?;
+	let selected = match arch {
+		Some(arch) => arches.iter().find(|(_, _, found)| *found == arch).ok_or(ImageError::ArchNotFound { arch })?,
+		None => MachOArch::current()
+			.and_then(|arch| arches.iter().find(|(_, _, found)| *found == arch))
+			.or_else(|| arches.first())
+			.ok_or_else(|| malformed("fat binary has no images"))?,
+	};
+	let (start, size, _) = *selected;
+	parse_thin(data, start, &data[start as usize..(start + size) as usize])
+}
+
+/// Parses a single Mach-O image at `start` in the file
+fn parse_thin<'a>(data: &'a [u8], start: u64, image: &[u8]) -> Result<Image<'a>, ImageError> {
+	let (wide, big_endian) = match Reader::new(image, true).u32(0)? {
+		MH_MAGIC => (false, true),
+		MH_MAGIC_64 => (true, true),
+		MH_CIGAM => (false, false),
+		MH_CIGAM_64 => (true, false),
+		_ => return Err(malformed("invalid Mach-O magic in fat binary")),
+	};
+	let file = Reader::new(image, big_endian);
+
+	let commands = file.u32(16)? as u64;
+	let mut command = if wide { 32 } else { 28 };
+
+	let mut base = None;
+	let mut segments = Vec::new();
+	let mut sections = Vec::new();
+	for _ in 0..commands {
+		let kind = file.u32(command)?;
+		let size = file.u32(command + 4)? as u64;
+		if size < 8 {
+			return Err(malformed("invalid load command size"));
+		}
+
+		if kind == LC_SEGMENT || kind == LC_SEGMENT_64 {
+			let wide = kind == LC_SEGMENT_64;
+			let w = if wide { 8 } else { 4 };
+			let name = read_name(file.bytes(command + 8, 16)?);
+			let address = file.word(command + 24, wide)?;
+			let memory_size = file.word(command + 24 + w, wide)?;
+			let offset = file.word(command + 24 + 2 * w, wide)?;
+			let file_size = file.word(command + 24 + 3 * w, wide)?;
+			let protection = file.u32(command + 24 + 4 * w + 4)?;
+			let count = file.u32(command + 24 + 4 * w + 8)? as u64;
+
+			let mut flags = SegmentFlags::NONE;
+			for (flag, bit) in [(SegmentFlags::R, VM_PROT_READ), (SegmentFlags::W, VM_PROT_WRITE), (SegmentFlags::X, VM_PROT_EXECUTE)].iter() {
+				if protection & bit != 0 {
+					flags = flags | *flag;
+				}
+			}
+			if name == "__TEXT" {
+				base = Some(address);
+			}
+
+			// Segments that can't be accessed, like __PAGEZERO, reserve address space but aren't loaded
+			if protection != 0 {
+				segments.push(ImageSection {
+					name,
+					address,
+					size: memory_size,
+					offset,
+					file_size: file_size.min(memory_size),
+					flags,
+				});
+			}
+
+			let (header_size, section_size) = if wide { (72, 80) } else { (56, 68) };
+			for index in 0..count {
+				let header = command + header_size + index * section_size;
+				let section_name = read_name(file.bytes(header, 16)?);
+				let segment_name = read_name(file.bytes(header + 16, 16)?);
+				let address = file.word(header + 32, wide)?;
+				let size = file.word(header + 32 + w, wide)?;
+				let offset = file.u32(header + 32 + 2 * w)? as u64;
+				let kind = file.u32(header + 32 + 2 * w + 4 * 4)? & SECTION_TYPE;
+
+				let zero_fill = matches!(kind, S_ZEROFILL | S_GB_ZEROFILL | S_THREAD_LOCAL_ZEROFILL);
+				sections.push(ImageSection {
+					name: format!("{},{}", segment_name, section_name),
+					address,
+					size,
+					offset: if zero_fill { 0 } else { offset },
+					file_size: if zero_fill { 0 } else { size },
+					flags,
+				});
+			}
+		}
+
+		command += size;
+	}
+
+	// Offsets are relative to the image, which may be in a fat binary
+	for section in segments.iter_mut().chain(sections.iter_mut()) {
+		Image::check_section(image, section)?;
+		section.offset += start;
+	}
+
+	let base = base.or_else(|| segments.iter().map(|segment| segment.address).min()).unwrap_or(0);
+	Image::new(data, ImageFormat::MachO, base, None, sections, segments)
+}
+
+/// Reads a name that is padded with zeros
+fn read_name(bytes: &[u8]) -> String {
+	String::from_utf8_lossy(&bytes[..bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len())]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{Image, ImageError, ImageFormat, ImageMatch, MachOArch, SegmentFlags, Signature};
+
+	/// Writes integers of either endianness
+	struct Writer {
+		file: Vec<u8>,
+		big_endian: bool,
+	}
+	impl Writer {
+		fn put(&mut self, value: u64, len: usize) {
+			let bytes = value.to_le_bytes();
+			if self.big_endian {
+				self.file.extend(bytes[..len].iter().rev());
+			} else {
+				self.file.extend(&bytes[..len]);
+			}
+		}
+
+		fn name(&mut self, name: &str) {
+			self.file.extend(name.as_bytes());
+			self.file.resize(self.file.len() + 16 - name.len(), 0);
+		}
+	}
+
+	const TEXT: &[u8] = &[0x55, 0x89, 0xE5, 0xE8, 0x10, 0x00, 0x00, 0x00, 0x5D, 0xC3, 0x55, 0x89, 0xE5];
+	const CSTRING: &[u8] = b"skidscan\0";
+
+	/// Builds an executable with __PAGEZERO, __TEXT at `text` (containing __text and __cstring) and __DATA after it
+	/// (containing __data and zero filled __bss)
+	fn build(wide: bool, big_endian: bool, text: u64) -> Vec<u8> {
+		let w = if wide { 8 } else { 4 };
+		let mut macho = Writer { file: Vec::new(), big_endian };
+		macho.put(if wide { 0xFEED_FACF } else { 0xFEED_FACE }, 4);
+		macho.put(if wide { 0x0100_0007 } else { 18 }, 4);
+		macho.put(3, 4);
+		macho.put(2, 4);
+		macho.put(3, 4);
+		macho.put(0, 4);
+		macho.put(0, 4);
+		if wide {
+			macho.put(0, 4);
+		}
+
+		// Name, address, size, offset, file size, protection and sections
+		let segments = [
+			("__PAGEZERO", 0, text, 0, 0, 0, &[][..]),
+			("__TEXT", text, 0x1000, 0, 0x500, 5, &[("__text", 0x400, TEXT.len() as u64, 0x8000_0400), ("__cstring", 0x420, CSTRING.len() as u64, 2)][..]),
+			("__DATA", text + 0x1000, 0x1000, 0x500, 0x100, 3, &[("__data", 0, 0x10, 0), ("__bss", 0x10, 0x20, 1)][..]),
+		];
+		for (name, address, size, offset, file_size, protection, sections) in segments.iter() {
+			let (header_size, section_size) = if wide { (72, 80) } else { (56, 68) };
+			macho.put(if wide { 0x19 } else { 0x1 }, 4);
+			macho.put(header_size + section_size * sections.len() as u64, 4);
+			macho.name(name);
+			for field in [*address, *size, *offset, *file_size].iter() {
+				macho.put(*field, w);
+			}
+			macho.put(*protection, 4);
+			macho.put(*protection, 4);
+			macho.put(sections.len() as u64, 4);
+			macho.put(0, 4);
+
+			for (section, start, size, flags) in sections.iter() {
+				macho.name(section);
+				macho.name(name);
+				macho.put(address + start, w);
+				macho.put(*size, w);
+				macho.put(if *flags == 1 { 0 } else { offset + start }, 4);
+				for field in [0, 0, 0, *flags, 0, 0].iter() {
+					macho.put(*field, 4);
+				}
+				if wide {
+					macho.put(0, 4);
+				}
+			}
+		}
+
+		let mut file = macho.file;
+		file.resize(0x600, 0);
+		file[0x400..0x400 + TEXT.len()].copy_from_slice(TEXT);
+		file[0x420..0x420 + CSTRING.len()].copy_from_slice(CSTRING);
+		file
+	}
+
+	#[test]
+	fn test_parse_macho() {
+		let text = 0x1_0000_0000;
+		let file = build(true, false, text);
+		let image = Image::parse(&file).unwrap();
+		assert_eq!(image.format(), ImageFormat::MachO);
+		assert_eq!(image.base(), text);
+		assert_eq!(image.segments().iter().map(|segment| segment.name.as_str()).collect::<Vec<_>>(), vec!["__TEXT", "__DATA"]);
+		assert_eq!(
+			image.sections().iter().map(|section| section.name.as_str()).collect::<Vec<_>>(),
+			vec!["__TEXT,__text", "__TEXT,__cstring", "__DATA,__data", "__DATA,__bss"]
+		);
+
+		let section = image.section("__TEXT,__text").unwrap();
+		assert_eq!((section.address, section.offset, section.flags), (text + 0x400, 0x400, SegmentFlags::RX));
+		assert_eq!(&*image.section_data(section), TEXT);
+		let bss = image.section("__DATA,__bss").unwrap();
+		assert_eq!((bss.size, bss.file_size, bss.flags), (0x20, 0, SegmentFlags::RW));
+
+		let signature = Signature::from(&[0xE8, 0x10][..]);
+		let found = ImageMatch {
+			address: text + 0x403,
+			rva: Some(0x403),
+			offset: Some(0x403),
+		};
+		assert_eq!(image.scan_unique_section("__TEXT,__text", &signature), Ok(found));
+		assert_eq!(image.scan_unique(&signature), Ok(found));
+		assert_eq!(image.scan_unique_section("__TEXT,__cstring", &Signature::from(&b"skidscan"[..])).map(|found| found.address), Ok(text + 0x420));
+		assert_eq!(image.scan_section("__DATA,__bss", &Signature::from(vec![0; 16])).unwrap()[0].offset, None);
+
+		let memory = image.map();
+		assert_eq!(memory.len(), 0x2000);
+		assert_eq!(&memory[0x400..0x400 + TEXT.len()], TEXT);
+
+		assert_eq!(Image::parse(&file[..0x480]), Err(ImageError::Malformed { reason: "section data is outside of the file" }));
+		assert_eq!(Image::parse(&file[..0x40]), Err(ImageError::Malformed { reason: "truncated" }));
+	}
+
+	#[test]
+	fn test_parse_macho_below_base() {
+		// The base is the address of __TEXT, so __DATA can't be mapped if it's below it
+		let text = 0x10000;
+		let mut file = build(true, false, text);
+		let data = file.windows(16).position(|name| name == b"__DATA\0\0\0\0\0\0\0\0\0\0").unwrap();
+		file[data + 16..data + 24].copy_from_slice(&0x1000u64.to_le_bytes());
+		assert_eq!(Image::parse(&file), Err(ImageError::Malformed { reason: "segment is below the image base" }));
+	}
+
+	#[test]
+	fn test_parse_fat_macho() {
+		let mut fat = Writer { file: Vec::new(), big_endian: true };
+		fat.put(0xCAFE_BABE, 4);
+		fat.put(2, 4);
+		for (cpu_type, offset) in [(0x0100_0007, 0x1000), (18, 0x2000)].iter() {
+			for field in [*cpu_type, 3, *offset, 0x600, 12].iter() {
+				fat.put(*field, 4);
+			}
+		}
+		let mut file = fat.file;
+		file.resize(0x1000, 0);
+		file.extend(build(true, false, 0x1_0000_0000));
+		file.resize(0x2000, 0);
+		file.extend(build(false, true, 0x1000));
+
+		assert_eq!(Image::macho_arches(&file), Ok(vec![MachOArch::X86_64, MachOArch::Other(18)]));
+		assert_eq!(Image::parse(&file).map(|image| image.base()), Ok(0x1_0000_0000));
+
+		// File offsets are in the fat binary
+		let image = Image::parse_macho_arch(&file, MachOArch::Other(18)).unwrap();
+		assert_eq!(image.base(), 0x1000);
+		let found = image.scan_unique_section("__TEXT,__text", &Signature::from(&[0xE8, 0x10][..])).unwrap();
+		assert_eq!((found.address, found.rva, found.offset), (0x1403, Some(0x403), Some(0x2403)));
+
+		assert_eq!(Image::parse_macho_arch(&file, MachOArch::Arm64), Err(ImageError::ArchNotFound { arch: MachOArch::Arm64 }));
+		assert_eq!(Image::parse_macho_arch(&file[..0x1800], MachOArch::X86_64), Err(ImageError::Malformed { reason: "fat binary image is outside of the file" }));
+
+		// Java class files have the same magic
+		assert_eq!(Image::parse(b"\xCA\xFE\xBA\xBE\0\0\0\x34"), Err(ImageError::UnknownFormat));
+	}
+}
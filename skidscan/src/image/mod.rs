@@ -0,0 +1,451 @@
+use crate::{SegmentFlags, Signature};
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+mod elf;
+mod macho;
+mod pe;
+
+pub use macho::MachOArch;
+
+/// The most memory a section or an image can take up when loaded, larger sizes are rejected so that mapping them can't exhaust memory
+const MAX_SIZE: u64 = 1 << 30;
+
+/// The file format of an [`Image`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+	/// ELF, 32 or 64-bit and either endianness
+	Elf,
+
+	/// PE/COFF, PE32 or PE32+
+	Pe,
+
+	/// Mach-O, 32 or 64-bit, on its own or in a fat (universal) binary
+	MachO,
+}
+
+/// An error parsing or scanning an [`Image`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ImageError {
+	/// The file isn't in a supported format
+	UnknownFormat,
+
+	/// The file is truncated, or one of its headers is invalid
+	Malformed { reason: &'static str },
+
+	/// The image has no section or segment with this name
+	SectionNotFound { name: String },
+
+	/// The fat binary has no image for this architecture
+	ArchNotFound { arch: MachOArch },
+
+	/// Failed to find the signature
+	NotFound,
+
+	/// Found multiple occurrences of the signature, the first two of which are at these virtual addresses
+	///
+	/// Scanning stops at the second match, so that a signature that matches every zero of a large section doesn't have to find all of them.
+	MultipleFound { addresses: Vec<u64> },
+}
+impl std::fmt::Display for ImageError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ImageError::UnknownFormat => f.write_str("unknown image format"),
+			ImageError::Malformed { reason } => write!(f, "malformed image: {}", reason),
+			ImageError::SectionNotFound { name } => write!(f, "image has no section or segment `{}`", name),
+			ImageError::ArchNotFound { arch } => write!(f, "fat binary has no image for {:?}", arch),
+			ImageError::NotFound => f.write_str("signature not found in image"),
+			ImageError::MultipleFound { addresses } => {
+				f.write_str("signature found multiple times in image, first at")?;
+				for (i, address) in addresses.iter().enumerate() {
+					write!(f, "{} {:#x}", if i == 0 { "" } else { "," }, address)?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+impl std::error::Error for ImageError {}
+
+/// A section or segment of an [`Image`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ImageSection {
+	/// The name of the section or segment, which is empty for the headers of a PE image
+	///
+	/// ELF segments are named after their type and the index of their program header, e.g. `LOAD2`.
+	pub name: String,
+
+	/// The virtual address of the section when the image is loaded at its preferred base
+	pub address: u64,
+
+	/// The size of the section in memory
+	pub size: u64,
+
+	/// The offset of the section's data in the file
+	pub offset: u64,
+
+	/// The size of the section's data in the file, the rest of the section is filled with zeros in memory
+	pub file_size: u64,
+
+	/// The memory protection of the section, which is empty for sections that aren't loaded
+	pub flags: SegmentFlags,
+}
+
+/// A signature found in an [`Image`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ImageMatch {
+	/// The virtual address of the match when the image is loaded at its preferred base
+	pub address: u64,
+
+	/// The address of the match relative to the image base, or `None` if it's in a section that isn't loaded (such as `.symtab`)
+	pub rva: Option<u64>,
+
+	/// The offset of the match in the file, or `None` if it starts in memory that is filled with zeros when loaded
+	pub offset: Option<u64>,
+}
+
+/// An executable image or shared library parsed from a file, which can be scanned without loading it
+///
+/// ```ignore
+/// let data = std::fs::read("libclient.so")?;
+/// let image = Image::parse(&data)?;
+/// let found: ImageMatch = image.scan_unique_section(".text", &signature!("E8 ? ? ? ? 48 85 C0"))?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Image<'a> {
+	data: &'a [u8],
+	format: ImageFormat,
+	base: u64,
+	size: u64,
+	sections: Vec<ImageSection>,
+	segments: Vec<ImageSection>,
+}
+impl<'a> Image<'a> {
+	/// Parses an image, detecting its format
+	///
+	/// Images that would take up more than 1 GiB of memory when loaded are rejected as malformed.
+	pub fn parse(data: &'a [u8]) -> Result<Self, ImageError> {
+		if data.starts_with(elf::MAGIC) {
+			Image::parse_elf(data)
+		} else if data.starts_with(pe::MAGIC) {
+			Image::parse_pe(data)
+		} else if macho::is_macho(data) {
+			Image::parse_macho(data)
+		} else {
+			Err(ImageError::UnknownFormat)
+		}
+	}
+
+	/// Parses an ELF image, either 32 or 64-bit and either endianness
+	///
+	/// Its segments are its `PT_LOAD` program headers, and its base is their lowest address, rounded down to a page.
+	#[inline]
+	pub fn parse_elf(data: &'a [u8]) -> Result<Self, ImageError> {
+		elf::parse(data)
+	}
+
+	/// Parses a PE image (a Windows executable or DLL), either PE32 or PE32+
+	///
+	/// Its segments are its headers and its sections, laid out as they are in memory: each section is at its RVA and is
+	/// rounded up to the section alignment, and its base is its preferred image base, so [`ImageMatch::rva`] is an RVA. Its size is its
+	/// `SizeOfImage`, which every section has to fit in.
+	#[inline]
+	pub fn parse_pe(data: &'a [u8]) -> Result<Self, ImageError> {
+		pe::parse(data)
+	}
+
+	/// Parses a Mach-O image, either 32 or 64-bit, or one of the images in a fat (universal) binary
+	///
+	/// From a fat binary, the image for the architecture skidscan was compiled for is parsed, or else the first one.
+	/// Use [`Image::parse_macho_arch`] to choose one.
+	///
+	/// Its segments are its `LC_SEGMENT` and `LC_SEGMENT_64` commands that are mapped with some access, its sections are
+	/// named after their segment, e.g. `__TEXT,__text`, and its base is the address of `__TEXT`.
+	#[inline]
+	pub fn parse_macho(data: &'a [u8]) -> Result<Self, ImageError> {
+		macho::parse(data, None)
+	}
+
+	/// Parses the Mach-O image for an architecture, from a fat (universal) binary or a single image
+	#[inline]
+	pub fn parse_macho_arch(data: &'a [u8], arch: MachOArch) -> Result<Self, ImageError> {
+		macho::parse(data, Some(arch))
+	}
+
+	/// Returns the architectures of the images in a fat (universal) binary, or of a single Mach-O image
+	pub fn macho_arches(data: &[u8]) -> Result<Vec<MachOArch>, ImageError> {
+		Ok(macho::arches(data)?.into_iter().map(|(_, _, arch)| arch).collect())
+	}
+
+	/// The format of the image
+	#[inline]
+	pub fn format(&self) -> ImageFormat {
+		self.format
+	}
+
+	/// The address the image prefers to be loaded at, which relative addresses are relative to
+	#[inline]
+	pub fn base(&self) -> u64 {
+		self.base
+	}
+
+	/// How much memory the image takes up when loaded, from its base to the end of its last segment or as declared by its headers
+	#[inline]
+	pub fn size(&self) -> u64 {
+		self.size
+	}
+
+	/// The sections of the image, in the order of its headers
+	#[inline]
+	pub fn sections(&self) -> &[ImageSection] {
+		&self.sections
+	}
+
+	/// The segments of the image that are loaded into memory, in the order of its headers
+	#[inline]
+	pub fn segments(&self) -> &[ImageSection] {
+		&self.segments
+	}
+
+	/// Returns the first section with this name
+	#[inline]
+	pub fn section(&self, name: &str) -> Option<&ImageSection> {
+		self.sections.iter().find(|section| section.name == name)
+	}
+
+	/// Returns the first segment with this name
+	#[inline]
+	pub fn segment(&self, name: &str) -> Option<&ImageSection> {
+		self.segments.iter().find(|segment| segment.name == name)
+	}
+
+	/// Returns the data of a section as it is in memory, filled with zeros after its data in the file
+	pub fn section_data(&self, section: &ImageSection) -> Cow<'a, [u8]> {
+		let data = self.file_data(section);
+		if section.size <= section.file_size {
+			Cow::Borrowed(data)
+		} else {
+			// Sections are checked to not be larger than `MAX_SIZE` when parsed
+			let mut memory = data.to_vec();
+			memory.resize(section.size as usize, 0);
+			Cow::Owned(memory)
+		}
+	}
+
+	/// Returns the image as it would be in memory when loaded at its base, with every segment at its address
+	pub fn map(&self) -> Vec<u8> {
+		let mut memory = vec![0; self.size as usize];
+		for segment in &self.segments {
+			let start = match segment.address.checked_sub(self.base) {
+				Some(start) if start < self.size => start as usize,
+				_ => continue,
+			};
+			let data = self.file_data(segment);
+			let len = data.len().min(memory.len() - start);
+			memory[start..start + len].copy_from_slice(&data[..len]);
+		}
+		memory
+	}
+
+	/// Scans every segment of the image, returning every occurrence of the signature in ascending order within each segment
+	#[inline]
+	pub fn scan_all(&self, signature: &Signature) -> Vec<ImageMatch> {
+		self.scan_segments(signature, None)
+	}
+
+	/// Scans the segments of the image, returning every occurrence of the signature in ascending order within each segment
+	///
+	/// If `flags` is given, only segments with exactly those flags are scanned (e.g. `SegmentFlags::RX` for code)
+	pub fn scan_segments(&self, signature: &Signature, flags: Option<SegmentFlags>) -> Vec<ImageMatch> {
+		self.segments
+			.iter()
+			.filter(|segment| flags.map(|flags| segment.flags == flags).unwrap_or(true))
+			.flat_map(|segment| self.scan_in(signature, segment))
+			.collect()
+	}
+
+	/// Scans a section of the image by name, e.g. `.text`, returning every occurrence of the signature in ascending order
+	pub fn scan_section(&self, name: &str, signature: &Signature) -> Result<Vec<ImageMatch>, ImageError> {
+		let section = self.section(name).ok_or_else(|| ImageError::SectionNotFound { name: name.to_string() })?;
+		Ok(self.scan_in(signature, section).collect())
+	}
+
+	/// Scans a segment of the image by name, e.g. `__TEXT` or `LOAD2`, returning every occurrence of the signature in ascending order
+	pub fn scan_segment(&self, name: &str, signature: &Signature) -> Result<Vec<ImageMatch>, ImageError> {
+		let segment = self.segment(name).ok_or_else(|| ImageError::SectionNotFound { name: name.to_string() })?;
+		Ok(self.scan_in(signature, segment).collect())
+	}
+
+	/// Scans every segment of the image for exactly one occurrence of the signature
+	#[inline]
+	pub fn scan_unique(&self, signature: &Signature) -> Result<ImageMatch, ImageError> {
+		unique(self.segments.iter().flat_map(|segment| self.scan_in(signature, segment)))
+	}
+
+	/// Scans a section of the image by name for exactly one occurrence of the signature
+	pub fn scan_unique_section(&self, name: &str, signature: &Signature) -> Result<ImageMatch, ImageError> {
+		let section = self.section(name).ok_or_else(|| ImageError::SectionNotFound { name: name.to_string() })?;
+		unique(self.scan_in(signature, section))
+	}
+
+	/// Scans a segment of the image by name for exactly one occurrence of the signature
+	pub fn scan_unique_segment(&self, name: &str, signature: &Signature) -> Result<ImageMatch, ImageError> {
+		let segment = self.segment(name).ok_or_else(|| ImageError::SectionNotFound { name: name.to_string() })?;
+		unique(self.scan_in(signature, segment))
+	}
+
+	/// Returns the data of a section that is in the file, which is all of it unless it's filled with zeros at the end
+	fn file_data(&self, section: &ImageSection) -> &'a [u8] {
+		// Sections are checked to be in the file when parsed
+		&self.data[section.offset as usize..(section.offset + section.file_size.min(section.size)) as usize]
+	}
+
+	/// Scans a section lazily, so that scanning can stop at any match
+	fn scan_in<'s>(&'s self, signature: &'s Signature, section: &'s ImageSection) -> impl Iterator<Item = ImageMatch> + 's {
+		let data = self.file_data(section);
+		let loaded = section.flags != SegmentFlags::NONE;
+		let base = self.base;
+		scan_zero_filled(signature, data, section.size - data.len() as u64).map(move |pos| ImageMatch {
+			address: section.address + pos,
+			rva: if loaded { (section.address + pos).checked_sub(base) } else { None },
+			offset: if pos < data.len() as u64 { Some(section.offset + pos) } else { None },
+		})
+	}
+
+	/// Creates an image from its parsed headers, checking that it isn't too large to map
+	///
+	/// Its size is `size` if its headers declare one, otherwise it ends with its last segment. Every segment has to be at or
+	/// above its base, since that's where it's mapped from.
+	pub(crate) fn new(
+		data: &'a [u8],
+		format: ImageFormat,
+		base: u64,
+		size: Option<u64>,
+		sections: Vec<ImageSection>,
+		segments: Vec<ImageSection>,
+	) -> Result<Self, ImageError> {
+		if segments.iter().any(|segment| segment.address < base) {
+			return Err(ImageError::Malformed { reason: "segment is below the image base" });
+		}
+		let end = segments.iter().map(|segment| segment.address + segment.size).max().unwrap_or(base);
+		let size = size.unwrap_or_else(|| end.saturating_sub(base));
+		if size > MAX_SIZE {
+			return Err(ImageError::Malformed { reason: "image is too large" });
+		}
+		Ok(Image {
+			data,
+			format,
+			base,
+			size,
+			sections,
+			segments,
+		})
+	}
+
+	/// Checks that a section is in the file and in the address space, and isn't too large to map, before adding it
+	pub(crate) fn check_section(data: &[u8], section: &ImageSection) -> Result<(), ImageError> {
+		let in_file = section.offset.checked_add(section.file_size).map(|end| end <= data.len() as u64).unwrap_or(false);
+		if !in_file {
+			return Err(ImageError::Malformed { reason: "section data is outside of the file" });
+		}
+		let in_memory = section.address.checked_add(section.size).and_then(|end| usize::try_from(end).ok()).is_some();
+		if !in_memory {
+			return Err(ImageError::Malformed { reason: "section is outside of the address space" });
+		}
+		if section.size > MAX_SIZE {
+			return Err(ImageError::Malformed { reason: "section is too large" });
+		}
+		Ok(())
+	}
+}
+
+/// Scans `data` followed by `zeros` zero bytes, yielding the position of every match in ascending order
+///
+/// Only as many zeros as the signature can span are allocated: matches that start in the zeros can only depend on how many
+/// zeros are left, and a match that fits in fewer zeros also fits in more. The end of the data and the zeros are only scanned
+/// once the matches before them have been used.
+fn scan_zero_filled<'a>(signature: &'a Signature, data: &'a [u8], zeros: u64) -> impl Iterator<Item = u64> + 'a {
+	// Matches that start more than a window before the end of the data can't reach the zeros
+	let window = signature.max_len().max(1);
+	let keep = if zeros == 0 { 0 } else { data.len().min(window - 1) };
+	let split = data.len() - keep;
+	let before = signature.scan_all(data).starting_before(split).map(|pos| pos as u64);
+
+	let boundary = std::iter::once(()).flat_map(move |_| {
+		let mut boundary = data[split..].to_vec();
+		boundary.resize(keep + zeros.min(window as u64 - 1) as usize, 0);
+		signature.scan_all(&boundary).starting_before(keep).map(|pos| (split + pos) as u64).collect::<Vec<_>>()
+	});
+
+	// The shortest run of zeros a match fits in gives the last match in the zeros, with every position before it matching too
+	let in_zeros = std::iter::once(()).flat_map(move |_| {
+		let run = zeros.min(window as u64) as usize;
+		let shortest = signature.scan_all(&vec![0; run]).last().map(|last| (run - last) as u64);
+		shortest.into_iter().flat_map(move |shortest| (0..=zeros - shortest).map(move |pos| data.len() as u64 + pos))
+	});
+
+	before.chain(boundary).chain(in_zeros)
+}
+
+/// Returns the only match, scanning no further than the second one
+fn unique(mut found: impl Iterator<Item = ImageMatch>) -> Result<ImageMatch, ImageError> {
+	let first = found.next().ok_or(ImageError::NotFound)?;
+	match found.next() {
+		None => Ok(first),
+		Some(second) => Err(ImageError::MultipleFound {
+			addresses: vec![first.address, second.address],
+		}),
+	}
+}
+
+/// Reads integers of either endianness from a file, failing if they are outside of it
+#[derive(Clone, Copy)]
+pub(crate) struct Reader<'a> {
+	data: &'a [u8],
+	big_endian: bool,
+}
+impl<'a> Reader<'a> {
+	#[inline]
+	pub(crate) fn new(data: &'a [u8], big_endian: bool) -> Self {
+		Reader { data, big_endian }
+	}
+
+	pub(crate) fn bytes(&self, offset: u64, len: u64) -> Result<&'a [u8], ImageError> {
+		let truncated = ImageError::Malformed { reason: "truncated" };
+		let start = usize::try_from(offset).map_err(|_| truncated.clone())?;
+		let end = offset.checked_add(len).and_then(|end| usize::try_from(end).ok()).ok_or_else(|| truncated.clone())?;
+		self.data.get(start..end).ok_or(truncated)
+	}
+
+	fn int(&self, offset: u64, len: u64) -> Result<u64, ImageError> {
+		let bytes = self.bytes(offset, len)?;
+		let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+		Ok(if self.big_endian {
+			bytes.iter().fold(0, fold)
+		} else {
+			bytes.iter().rev().fold(0, fold)
+		})
+	}
+
+	#[inline]
+	pub(crate) fn u16(&self, offset: u64) -> Result<u16, ImageError> {
+		self.int(offset, 2).map(|value| value as u16)
+	}
+
+	#[inline]
+	pub(crate) fn u32(&self, offset: u64) -> Result<u32, ImageError> {
+		self.int(offset, 4).map(|value| value as u32)
+	}
+
+	#[inline]
+	pub(crate) fn u64(&self, offset: u64) -> Result<u64, ImageError> {
+		self.int(offset, 8)
+	}
+
+	/// Reads a 32-bit integer, or a 64-bit one if `wide`
+	#[inline]
+	pub(crate) fn word(&self, offset: u64, wide: bool) -> Result<u64, ImageError> {
+		self.int(offset, if wide { 8 } else { 4 })
+	}
+}
@@ -0,0 +1,237 @@
+use super::{Image, ImageError, ImageFormat, ImageSection, Reader};
+use crate::SegmentFlags;
+
+pub(super) const MAGIC: &[u8] = b"MZ";
+
+const PE32: u16 = 0x10B;
+const PE32_PLUS: u16 = 0x20B;
+
+const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+fn malformed(reason: &'static str) -> ImageError {
+	ImageError::Malformed { reason }
+}
+
+/// Rounds up to a multiple of `alignment`, which the loader treats as 1 if it is 0
+fn align_up(value: u64, alignment: u64) -> u64 {
+	match alignment {
+		0 | 1 => value,
+		_ => match value % alignment {
+			0 => value,
+			rest => value + (alignment - rest),
+		},
+	}
+}
+
+pub(super) fn parse(data: &[u8]) -> Result<Image<'_>, ImageError> {
+	if !data.starts_with(MAGIC) {
+		return Err(ImageError::UnknownFormat);
+	}
+	let file = Reader::new(data, false);
+
+	let pe = file.u32(0x3C)? as u64;
+	if file.bytes(pe, 4)? != b"PE\0\0" {
+		return Err(malformed("invalid PE signature"));
+	}
+	let sections = file.u16(pe + 6)? as u64;
+	let optional = pe + 24;
+	let table = optional + file.u16(pe + 20)? as u64;
+
+	let base = match file.u16(optional)? {
+		PE32 => file.u32(optional + 28)? as u64,
+		PE32_PLUS => file.u64(optional + 24)?,
+		_ => return Err(malformed("invalid optional header magic")),
+	};
+	let section_alignment = file.u32(optional + 32)? as u64;
+	let image_size = file.u32(optional + 56)? as u64;
+	let headers_size = file.u32(optional + 60)? as u64;
+
+	// The loader maps `SizeOfImage` bytes, every section has to fit in them
+	let check = |section: &ImageSection| -> Result<(), ImageError> {
+		Image::check_section(data, section)?;
+		if section.address - base + section.size > image_size {
+			return Err(malformed("section is outside of the image"));
+		}
+		Ok(())
+	};
+
+	// The headers are mapped at the base, followed by each section at its RVA
+	let headers = ImageSection {
+		name: String::new(),
+		address: base,
+		size: align_up(headers_size, section_alignment),
+		offset: 0,
+		file_size: headers_size.min(data.len() as u64),
+		flags: SegmentFlags::R,
+	};
+	check(&headers)?;
+	let mut segments = vec![headers];
+
+	for index in 0..sections {
+		let header = table + index * 40;
+		let name = file.bytes(header, 8)?;
+		let name = &name[..name.iter().position(|byte| *byte == 0).unwrap_or(name.len())];
+		let virtual_size = file.u32(header + 8)? as u64;
+		let rva = file.u32(header + 12)? as u64;
+		let raw_size = file.u32(header + 16)? as u64;
+		let raw_offset = file.u32(header + 20)? as u64;
+		let characteristics = file.u32(header + 36)?;
+
+		let mut flags = SegmentFlags::NONE;
+		for (flag, bit) in [(SegmentFlags::R, IMAGE_SCN_MEM_READ), (SegmentFlags::W, IMAGE_SCN_MEM_WRITE), (SegmentFlags::X, IMAGE_SCN_MEM_EXECUTE)].iter() {
+			if characteristics & bit != 0 {
+				flags = flags | *flag;
+			}
+		}
+
+		// Sections take up whole multiples of the section alignment in memory, filled with zeros after their raw data
+		let size = align_up(if virtual_size == 0 { raw_size } else { virtual_size }, section_alignment);
+		let section = ImageSection {
+			name: String::from_utf8_lossy(name).into_owned(),
+			address: base.checked_add(rva).ok_or_else(|| malformed("section is outside of the address space"))?,
+			size,
+			offset: raw_offset,
+			file_size: if raw_offset == 0 { 0 } else { raw_size.min(size) },
+			flags,
+		};
+		check(&section)?;
+		segments.push(section);
+	}
+
+	Image::new(data, ImageFormat::Pe, base, Some(image_size), segments[1..].to_vec(), segments)
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{Image, ImageError, ImageFormat, ImageMatch, SegmentFlags, Signature};
+
+	const TEXT: &[u8] = &[0x55, 0x89, 0xE5, 0xE8, 0x10, 0x00, 0x00, 0x00, 0x5D, 0xC3, 0x55, 0x89, 0xE5];
+	const RDATA: &[u8] = b"skidscan\0";
+
+	fn put(file: &mut [u8], offset: usize, value: u64, len: usize) {
+		file[offset..offset + len].copy_from_slice(&value.to_le_bytes()[..len]);
+	}
+
+	/// Builds a PE with 0x200 file alignment and 0x1000 section alignment, made up of `.text`, `.rdata` and `.data`, which has no
+	/// data in the file
+	fn build(wide: bool, base: u64) -> Vec<u8> {
+		let mut file = vec![0; 0x600];
+		file[..2].copy_from_slice(b"MZ");
+		put(&mut file, 0x3C, 0x40, 4);
+
+		file[0x40..0x44].copy_from_slice(b"PE\0\0");
+		let optional_size = if wide { 112 } else { 96 };
+		put(&mut file, 0x44, if wide { 0x8664 } else { 0x14C }, 2);
+		put(&mut file, 0x46, 3, 2);
+		put(&mut file, 0x54, optional_size as u64, 2);
+
+		let optional = 0x58;
+		put(&mut file, optional, if wide { 0x20B } else { 0x10B }, 2);
+		if wide {
+			put(&mut file, optional + 24, base, 8);
+		} else {
+			put(&mut file, optional + 28, base, 4);
+		}
+		put(&mut file, optional + 32, 0x1000, 4);
+		put(&mut file, optional + 36, 0x200, 4);
+		put(&mut file, optional + 56, 0x4000, 4);
+		put(&mut file, optional + 60, 0x200, 4);
+
+		// .text (CODE|EXECUTE|READ), .rdata (INITIALIZED_DATA|READ), .data (UNINITIALIZED_DATA|READ|WRITE)
+		let sections = [
+			(".text", TEXT.len() as u64, 0x1000, 0x200, 0x200, 0x6000_0020),
+			(".rdata", RDATA.len() as u64, 0x2000, 0x200, 0x400, 0x4000_0040),
+			(".data", 0x100, 0x3000, 0, 0, 0xC000_0080),
+		];
+		let table = optional + optional_size;
+		for (i, (name, virtual_size, rva, raw_size, raw_offset, characteristics)) in sections.iter().enumerate() {
+			let header = table + i * 40;
+			file[header..header + name.len()].copy_from_slice(name.as_bytes());
+			put(&mut file, header + 8, *virtual_size, 4);
+			put(&mut file, header + 12, *rva, 4);
+			put(&mut file, header + 16, *raw_size, 4);
+			put(&mut file, header + 20, *raw_offset, 4);
+			put(&mut file, header + 36, *characteristics, 4);
+		}
+
+		file[0x200..0x200 + TEXT.len()].copy_from_slice(TEXT);
+		file[0x400..0x400 + RDATA.len()].copy_from_slice(RDATA);
+		file
+	}
+
+	#[test]
+	fn test_parse_pe() {
+		for (wide, base) in [(false, 0x40_0000), (true, 0x1_4000_0000)].iter() {
+			let file = build(*wide, *base);
+			let image = Image::parse(&file).unwrap();
+			assert_eq!(image.format(), ImageFormat::Pe);
+			assert_eq!(image.base(), *base);
+			assert_eq!(image.sections().iter().map(|section| section.name.as_str()).collect::<Vec<_>>(), vec![".text", ".rdata", ".data"]);
+			assert_eq!(image.segments().len(), 4);
+			assert_eq!((image.segments()[0].address, image.segments()[0].size), (*base, 0x1000));
+
+			// Sections are rounded up to the section alignment in memory, and all of their raw data is loaded from the file even
+			// if it's longer than their virtual size
+			let text = image.section(".text").unwrap();
+			assert_eq!((text.address, text.size, text.offset, text.file_size, text.flags), (base + 0x1000, 0x1000, 0x200, 0x200, SegmentFlags::RX));
+			assert_eq!(&image.section_data(text)[..TEXT.len()], TEXT);
+			let data = image.section(".data").unwrap();
+			assert_eq!((data.size, data.file_size, data.flags), (0x1000, 0, SegmentFlags::RW));
+			assert_eq!(image.section(".rdata").unwrap().flags, SegmentFlags::R);
+
+			let signature = Signature::from(&[0xE8, 0x10][..]);
+			let found = ImageMatch {
+				address: base + 0x1003,
+				rva: Some(0x1003),
+				offset: Some(0x203),
+			};
+			assert_eq!(image.scan_unique_section(".text", &signature), Ok(found));
+			assert_eq!(image.scan_unique(&signature), Ok(found));
+			assert_eq!(image.scan_segments(&signature, Some(SegmentFlags::R)), vec![]);
+			assert_eq!(image.scan_unique_section(".rdata", &Signature::from(&b"skidscan"[..])).map(|found| found.rva), Ok(Some(0x2000)));
+
+			let prologue = Signature::from(&[0x55, 0x89, 0xE5][..]);
+			assert_eq!(
+				image.scan_unique_section(".text", &prologue),
+				Err(ImageError::MultipleFound {
+					addresses: vec![base + 0x1000, base + 0x100A]
+				})
+			);
+
+			// .data is only zeros in memory
+			let zeros = Signature::from(vec![0; 16]);
+			assert_eq!(image.scan_section(".data", &zeros).unwrap()[0].offset, None);
+
+			let memory = image.map();
+			assert_eq!((image.size(), memory.len()), (0x4000, 0x4000));
+			assert_eq!(&memory[..2], b"MZ");
+			assert_eq!(&memory[0x1000..0x1000 + TEXT.len()], TEXT);
+			assert_eq!(&memory[0x2000..0x2000 + RDATA.len()], RDATA);
+
+			assert_eq!(Image::parse(&file[..0x400]), Err(ImageError::Malformed { reason: "section data is outside of the file" }));
+			assert_eq!(Image::parse(&file[..0x60]), Err(ImageError::Malformed { reason: "truncated" }));
+		}
+
+		let mut file = build(false, 0x40_0000);
+		file[0x40] = b'N';
+		assert_eq!(Image::parse_pe(&file), Err(ImageError::Malformed { reason: "invalid PE signature" }));
+	}
+
+	#[test]
+	fn test_parse_pe_image_size() {
+		// The image is mapped with the size its headers declare, even past its last section
+		let mut file = build(true, 0x1_4000_0000);
+		put(&mut file, 0x58 + 56, 0x6000, 4);
+		let image = Image::parse(&file).unwrap();
+		assert_eq!((image.size(), image.map().len()), (0x6000, 0x6000));
+
+		// .data ends at 0x4000, past a smaller image
+		put(&mut file, 0x58 + 56, 0x3800, 4);
+		assert_eq!(Image::parse(&file), Err(ImageError::Malformed { reason: "section is outside of the image" }));
+
+		put(&mut file, 0x58 + 56, 0xFFFF_F000, 4);
+		assert_eq!(Image::parse(&file), Err(ImageError::Malformed { reason: "image is too large" }));
+	}
+}
@@ -5,16 +5,68 @@ pub use skidscan_macros::*;
 #[cfg(feature = "obfuscate")]
 pub use obfstr::obfstr;
 
+mod matcher;
+
 mod signatures;
 pub use signatures::*;
 
+mod format;
+pub use format::SignatureFormat;
+
+mod set;
+pub use set::*;
+
+mod address;
+pub use address::{Address, Pod, ResolveError};
+
+mod x86;
+pub use x86::Arch;
+
+mod generator;
+pub use generator::{GenerateError, SignatureGenerator};
+
+mod stream;
+pub use stream::{StreamMatch, StreamPattern, StreamScanner};
+
+mod file;
+pub use file::FileScanner;
+
+mod parallel;
+pub use parallel::ParallelScanner;
+
+mod image;
+pub use image::{Image, ImageError, ImageFormat, ImageMatch, ImageSection, MachOArch};
+
+#[cfg(target_os = "linux")]
+mod process;
+#[cfg(target_os = "linux")]
+pub use process::{MemoryRegion, Process, ProcessError, ProcessModule};
+
 mod modulescan;
-pub use modulescan::ModuleSigScanError;
+pub use modulescan::{modules, Module, ModuleInfo, ModuleMatch, ModuleSelector, ModuleSigScanError, ModuleSigScanErrorKind, Segment, SegmentFlags};
 
 pub trait SigscanPtr: Copy + Ord {
 	unsafe fn next(self) -> Self;
 	unsafe fn byte(self) -> u8;
 	unsafe fn rewind(self, bytes: usize) -> Self;
+
+	/// Moves the pointer `bytes` bytes forward, one at a time unless overridden
+	#[inline]
+	unsafe fn forward(self, bytes: usize) -> Self {
+		let mut ptr = self;
+		for _ in 0..bytes {
+			ptr = ptr.next();
+		}
+		ptr
+	}
+
+	/// The address the pointer points to, if the memory it points to can be read directly
+	///
+	/// Otherwise, signatures are scanned in a copy of the memory read with [`SigscanPtr::byte`].
+	#[inline]
+	fn as_ptr(self) -> Option<*const u8> {
+		None
+	}
 }
 impl SigscanPtr for *const u8 {
 	#[inline(always)]
@@ -29,6 +81,14 @@ impl SigscanPtr for *const u8 {
 	unsafe fn rewind(self, bytes: usize) -> Self {
 		self.sub(bytes)
 	}
+	#[inline(always)]
+	unsafe fn forward(self, bytes: usize) -> Self {
+		self.add(bytes)
+	}
+	#[inline(always)]
+	fn as_ptr(self) -> Option<*const u8> {
+		Some(self)
+	}
 }
 impl SigscanPtr for *mut u8 {
 	#[inline(always)]
@@ -43,19 +103,47 @@ impl SigscanPtr for *mut u8 {
 	unsafe fn rewind(self, bytes: usize) -> Self {
 		self.sub(bytes)
 	}
+	#[inline(always)]
+	unsafe fn forward(self, bytes: usize) -> Self {
+		self.add(bytes)
+	}
+	#[inline(always)]
+	fn as_ptr(self) -> Option<*const u8> {
+		Some(self as *const u8)
+	}
 }
 
-trait SigScan {
+pub trait SigScan {
 	/// Scans this slice of bytes for a given signature
 	///
 	/// Returns the index of the first occurrence of the signature in the slice, or None if not found
 	fn sigscan(&self, signature: &Signature) -> Option<usize>;
+
+	/// Scans this slice of bytes for every occurrence of a given signature
+	///
+	/// See [`Signature::scan_all`]
+	fn sigscan_all<'a>(&'a self, signature: &'a Signature) -> ScanAll<'a>;
+
+	/// Scans this slice of bytes for exactly one occurrence of a given signature
+	///
+	/// See [`Signature::scan_unique`]
+	fn sigscan_unique(&self, signature: &Signature) -> Result<usize, UniqueScanError>;
 }
 impl<B: AsRef<[u8]>> SigScan for B {
 	#[inline(always)]
 	fn sigscan(&self, signature: &Signature) -> Option<usize> {
 		signature.scan(self.as_ref())
 	}
+
+	#[inline(always)]
+	fn sigscan_all<'a>(&'a self, signature: &'a Signature) -> ScanAll<'a> {
+		signature.scan_all(self.as_ref())
+	}
+
+	#[inline(always)]
+	fn sigscan_unique(&self, signature: &Signature) -> Result<usize, UniqueScanError> {
+		signature.scan_unique(self.as_ref())
+	}
 }
 
 #[cfg(test)]
@@ -0,0 +1,250 @@
+//! The matcher shared by every scan routine.
+//!
+//! Every candidate position is checked against the whole signature independently of any
+//! other position, so a partial match can never cause a later match to be skipped.
+//!
+//! Candidates are found by searching for the rarest fixed byte of the signature (the "anchor")
+//! with SSE2/AVX2 where available, and only then is the rest of the signature checked.
+//! Gaps and alternatives are matched by backtracking from each candidate.
+
+use crate::signatures::{SigByte, SigElement};
+
+/// Bytes that are very common in x86 machine code and data, most common first
+///
+/// Any byte not listed here is considered rare.
+const COMMON_BYTES: &[u8] = &[
+	0x00, 0xFF, 0x48, 0x8B, 0x89, 0xCC, 0x0F, 0x24, 0x4C, 0x44, 0x8D, 0x85, 0x83, 0xE8, 0x01, 0x45,
+	0x40, 0x74, 0x75, 0xC3, 0x20, 0x10, 0x08, 0x04, 0x41, 0x49, 0x4D, 0xC0, 0x90, 0x55, 0x5D, 0x50,
+	0x18, 0x28, 0x30, 0x38, 0x33, 0xEB, 0x80, 0xE9, 0x02, 0x03, 0xC7, 0x84, 0xF8, 0x6A, 0x68, 0x5E,
+];
+
+/// How common a byte is, lower is rarer
+#[inline]
+pub(crate) fn commonness(byte: u8) -> usize {
+	COMMON_BYTES
+		.iter()
+		.position(|common| *common == byte)
+		.map(|rank| COMMON_BYTES.len() - rank)
+		.unwrap_or(0)
+}
+
+/// The bytes of the signature that are at a fixed offset from the start of every match, with their offsets
+///
+/// These are the bytes before the first element that can match a varying number of bytes, so only they can be used as anchors.
+pub(crate) fn fixed_bytes(signature: &[SigElement]) -> impl Iterator<Item = (usize, SigByte)> + '_ {
+	signature
+		.iter()
+		.take_while(|element| element.min_len() == element.max_len())
+		.scan(0, |offset, element| {
+			let at = *offset;
+			*offset += element.min_len();
+			Some((at, element.as_byte()))
+		})
+		.filter_map(|(offset, byte)| Some((offset, byte?)))
+}
+
+/// Picks the rarest fixed byte of the signature, returning its offset and value
+///
+/// Only bytes that match exactly one value can be anchors.
+fn anchor(signature: &[SigElement]) -> Option<(usize, u8)> {
+	fixed_bytes(signature)
+		.filter_map(|(offset, byte)| byte.as_exact().map(|byte| (offset, byte)))
+		.min_by_key(|(_, byte)| commonness(*byte))
+}
+
+/// The gaps and captures of a match, in the order they were matched
+#[derive(Default)]
+pub(crate) struct Trace<'s> {
+	/// The length of each gap
+	pub(crate) gaps: Vec<usize>,
+
+	/// The name, offset and length of each capture
+	pub(crate) captures: Vec<(&'s str, usize, usize)>,
+}
+impl Trace<'_> {
+	#[inline]
+	pub(crate) fn clear(&mut self) {
+		self.gaps.clear();
+		self.captures.clear();
+	}
+
+	/// Returns a mark to backtrack to with [`Trace::reset`]
+	#[inline]
+	fn mark(&self) -> (usize, usize) {
+		(self.gaps.len(), self.captures.len())
+	}
+
+	#[inline]
+	fn reset(&mut self, (gaps, captures): (usize, usize)) {
+		self.gaps.truncate(gaps);
+		self.captures.truncate(captures);
+	}
+}
+
+/// The elements left to match after a group, and after the group that group is in, and so on
+struct Then<'s, 'a> {
+	elements: &'s [SigElement],
+	next: Option<&'a Then<'s, 'a>>,
+}
+
+/// Matches `signature` against `bytes` starting at `pos`, returning the end of the match
+///
+/// Gaps match as few bytes as possible and alternatives are tried in order, backtracking when the rest of the signature doesn't match.
+/// The gaps and captures that were used are pushed to `trace`.
+#[inline]
+pub(crate) fn match_at<'s>(signature: &'s [SigElement], bytes: &[u8], pos: usize, trace: &mut Trace<'s>) -> Option<usize> {
+	match_then(signature, None, bytes, pos, trace)
+}
+
+fn match_then<'s>(signature: &'s [SigElement], then: Option<&Then<'s, '_>>, bytes: &[u8], mut pos: usize, trace: &mut Trace<'s>) -> Option<usize> {
+	for (i, element) in signature.iter().enumerate() {
+		match element {
+			SigElement::Byte(sig_byte) => {
+				if !sig_byte.matches(*bytes.get(pos)?) {
+					return None;
+				}
+				pos += 1;
+			}
+			SigElement::Capture { name, len } => {
+				if bytes.len().checked_sub(pos)? < *len {
+					return None;
+				}
+				trace.captures.push((name, pos, *len));
+				pos += len;
+			}
+			SigElement::Gap { min, max } => {
+				let rest = &signature[i + 1..];
+				let max = (*max).min(bytes.len().checked_sub(pos)?);
+				let mark = trace.mark();
+				for len in *min..=max {
+					trace.reset(mark);
+					trace.gaps.push(len);
+					if let Some(end) = match_then(rest, then, bytes, pos + len, trace) {
+						return Some(end);
+					}
+				}
+				trace.reset(mark);
+				return None;
+			}
+			SigElement::Alt(alternatives) => {
+				let rest = Then {
+					elements: &signature[i + 1..],
+					next: then,
+				};
+				let mark = trace.mark();
+				for alternative in alternatives {
+					trace.reset(mark);
+					if let Some(end) = match_then(alternative, Some(&rest), bytes, pos, trace) {
+						return Some(end);
+					}
+				}
+				trace.reset(mark);
+				return None;
+			}
+		}
+	}
+	match then {
+		Some(then) => match_then(then.elements, then.next, bytes, pos, trace),
+		None => Some(pos),
+	}
+}
+
+/// Returns the offset of the first occurrence of `signature` in `bytes` that starts at or after `from` and before `before`
+///
+/// An empty signature never matches.
+#[inline]
+pub(crate) fn find(signature: &[SigElement], bytes: &[u8], from: usize, before: usize) -> Option<usize> {
+	find_match(signature, bytes, from, before, &mut Trace::default()).map(|(start, _)| start)
+}
+
+/// Returns the start and end of the first occurrence of `signature` in `bytes` that starts at or after `from` and before `before`
+///
+/// The gaps and captures of the match are left in `trace`. An empty signature never matches.
+pub(crate) fn find_match<'s>(signature: &'s [SigElement], bytes: &[u8], from: usize, before: usize, trace: &mut Trace<'s>) -> Option<(usize, usize)> {
+	if signature.is_empty() {
+		return None;
+	}
+	let min_len = signature.iter().map(SigElement::min_len).fold(0, usize::saturating_add);
+	let last = bytes.len().checked_sub(min_len)?.min(before.checked_sub(1)?);
+	if from > last {
+		return None;
+	}
+
+	let mut check = |candidate: usize| {
+		trace.clear();
+		match_at(signature, bytes, candidate, trace).map(|end| (candidate, end))
+	};
+
+	let (anchor_idx, anchor_byte) = match anchor(signature) {
+		Some(anchor) => anchor,
+		None => return (from..=last).find_map(check),
+	};
+
+	// Anchor candidates are searched for in the window of bytes where the anchor of a match could be
+	let window = &bytes[anchor_idx..last + anchor_idx + 1];
+	let mut pos = from;
+	while let Some(found) = memchr(anchor_byte, &window[pos..]) {
+		let candidate = pos + found;
+		if let Some(found) = check(candidate) {
+			return Some(found);
+		}
+		pos = candidate + 1;
+	}
+	None
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`
+#[inline]
+pub(crate) fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+	#[cfg(target_arch = "x86_64")]
+	{
+		if std::is_x86_feature_detected!("avx2") {
+			return unsafe { x86_64::memchr_avx2(needle, haystack) };
+		}
+		unsafe { x86_64::memchr_sse2(needle, haystack) }
+	}
+	#[cfg(not(target_arch = "x86_64"))]
+	{
+		memchr_scalar(needle, haystack)
+	}
+}
+
+#[inline]
+pub(crate) fn memchr_scalar(needle: u8, haystack: &[u8]) -> Option<usize> {
+	haystack.iter().position(|byte| *byte == needle)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86_64 {
+	use std::arch::x86_64::*;
+
+	#[target_feature(enable = "avx2")]
+	pub(super) unsafe fn memchr_avx2(needle: u8, haystack: &[u8]) -> Option<usize> {
+		let needle_vec = _mm256_set1_epi8(needle as i8);
+		let mut i = 0;
+		while i + 32 <= haystack.len() {
+			let chunk = _mm256_loadu_si256(haystack.as_ptr().add(i) as *const __m256i);
+			let mask = _mm256_movemask_epi8(_mm256_cmpeq_epi8(chunk, needle_vec)) as u32;
+			if mask != 0 {
+				return Some(i + mask.trailing_zeros() as usize);
+			}
+			i += 32;
+		}
+		memchr_sse2(needle, &haystack[i..]).map(|found| i + found)
+	}
+
+	#[target_feature(enable = "sse2")]
+	pub(super) unsafe fn memchr_sse2(needle: u8, haystack: &[u8]) -> Option<usize> {
+		let needle_vec = _mm_set1_epi8(needle as i8);
+		let mut i = 0;
+		while i + 16 <= haystack.len() {
+			let chunk = _mm_loadu_si128(haystack.as_ptr().add(i) as *const __m128i);
+			let mask = _mm_movemask_epi8(_mm_cmpeq_epi8(chunk, needle_vec)) as u32;
+			if mask != 0 {
+				return Some(i + mask.trailing_zeros() as usize);
+			}
+			i += 16;
+		}
+		super::memchr_scalar(needle, &haystack[i..]).map(|found| i + found)
+	}
+}
@@ -1,111 +1,270 @@
-use crate::ModuleSigScanError;
+use crate::{ModuleInfo, Segment, SegmentFlags};
 
-use std::ffi::{c_void, CStr, CString};
-use std::os::raw::{c_char, c_int};
+use std::ffi::{c_void, CStr, OsStr};
+use std::os::raw::c_int;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
 
 use libc::{dl_iterate_phdr, dl_phdr_info, PT_LOAD};
 
-#[repr(C)]
-struct CallbackData {
-	module_name_ptr: *const c_char,
-	memory_start: usize,
-	memory_len: usize,
-	memory_area: Option<&'static [u8]>,
-}
-
-pub struct Scanner {
-	module_name: String,
-}
-
 #[cfg(target_pointer_width = "32")]
 type Phdr = libc::Elf32_Phdr;
 #[cfg(target_pointer_width = "64")]
 type Phdr = libc::Elf64_Phdr;
 
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+impl From<u32> for SegmentFlags {
+	fn from(p_flags: u32) -> Self {
+		let mut flags = SegmentFlags::NONE;
+		if p_flags & PF_R != 0 {
+			flags = flags | SegmentFlags::R;
+		}
+		if p_flags & PF_W != 0 {
+			flags = flags | SegmentFlags::W;
+		}
+		if p_flags & PF_X != 0 {
+			flags = flags | SegmentFlags::X;
+		}
+		flags
+	}
+}
+
+// This must never panic, as unwinding out of it would abort the process
 extern "C" fn dl_phdr_callback(info: *mut dl_phdr_info, _size: usize, data: *mut c_void) -> c_int {
 	let info = unsafe { *info };
-	let module_name = unsafe { CStr::from_ptr(info.dlpi_name) }.to_str().unwrap();
-	let cb_data = unsafe { &mut *(data as *mut CallbackData) };
-	let target_module_name = unsafe { CStr::from_ptr(cb_data.module_name_ptr as *mut c_char) }
-		.to_str()
-		.unwrap();
-	if !module_name.ends_with(target_module_name) {
-		return 0;
+	let modules = unsafe { &mut *(data as *mut Vec<ModuleInfo>) };
+
+	let mut path = if info.dlpi_name.is_null() {
+		PathBuf::new()
+	} else {
+		PathBuf::from(OsStr::from_bytes(unsafe { CStr::from_ptr(info.dlpi_name) }.to_bytes()))
+	};
+	if path.as_os_str().is_empty() && modules.is_empty() {
+		// The main executable is always first and has no name
+		if let Ok(exe) = std::fs::read_link("/proc/self/exe") {
+			path = exe;
+		}
 	}
 
-	let headers: &'static [Phdr] =
-		unsafe { std::slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize) };
-	let elf_header = headers
+	let headers: &'static [Phdr] = if info.dlpi_phdr.is_null() {
+		&[]
+	} else {
+		unsafe { std::slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize) }
+	};
+	let segments: Vec<Segment> = headers
 		.iter()
-		.find(|p| p.p_type == PT_LOAD)
-		.unwrap();
+		.filter(|p| p.p_type == PT_LOAD && p.p_memsz != 0)
+		.map(|p| Segment {
+			address: (info.dlpi_addr as usize).wrapping_add(p.p_vaddr as usize),
+			len: p.p_memsz as usize,
+			flags: SegmentFlags::from(p.p_flags),
+		})
+		.collect();
 
-	let start = info.dlpi_addr as usize + elf_header.p_vaddr as usize;
-	let end = start + elf_header.p_memsz as usize;
-	let len = end - start;
+	let start = segments.iter().map(|segment| segment.address).min().unwrap_or(0);
+	let end = segments.iter().map(|segment| segment.address.saturating_add(segment.len)).max().unwrap_or(0);
 
-	cb_data.memory_start = start;
-	cb_data.memory_len = len;
-	cb_data.memory_area = Some(unsafe { std::slice::from_raw_parts(start as *const u8, len) });
+	modules.push(ModuleInfo {
+		name: path.file_name().map(OsStr::to_os_string).unwrap_or_default(),
+		path,
+		base: info.dlpi_addr as usize,
+		size: end - start,
+		segments,
+	});
 	0
 }
 
-impl Scanner {
-	pub fn for_module(name: &str) -> Option<Scanner> {
-		Some(Scanner {
-			module_name: name.to_string(),
-		})
+/// Returns every module loaded in this process, in load order
+pub fn modules() -> Vec<ModuleInfo> {
+	let mut modules: Vec<ModuleInfo> = Vec::new();
+	unsafe { dl_iterate_phdr(Some(dl_phdr_callback), &mut modules as *mut Vec<ModuleInfo> as *mut c_void) };
+	modules
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{GenerateError, Image, Module, ModuleSelector, ModuleSigScanError, ModuleSigScanErrorKind, ParallelScanner, SegmentFlags, SigElement, Signature, SignatureGenerator};
+
+	/// Builds a signature from the first bytes of a function in libc, which lives in libc's executable segment
+	fn libc_function_signature() -> (*mut u8, Signature) {
+		let function = libc::dl_iterate_phdr as *mut u8;
+		let bytes = unsafe { std::slice::from_raw_parts(function, 48) };
+		(function, Signature::from(bytes))
 	}
 
-	pub fn find(&self, signature: &[Option<u8>]) -> Result<*mut u8, ModuleSigScanError> {
-		let module_name = CString::new(self.module_name.clone()).unwrap();
-		let module_name_ptr = module_name.as_ptr();
-		let mut data = CallbackData {
-			module_name_ptr,
-			memory_start: 0,
-			memory_len: 0,
-			memory_area: None,
-		};
-		unsafe { dl_iterate_phdr(Some(dl_phdr_callback), &mut data as *mut CallbackData as *mut c_void) };
-
-		let mut data_current = data.memory_start as *mut u8;
-		let data_end = (data.memory_start + data.memory_len) as *mut u8;
-
-		if data_current.is_null() || data_end == data_current {
-			return Err(ModuleSigScanError::InvalidModule);
-		}
+	#[test]
+	fn test_scan_module_every_segment() {
+		let (function, signature) = libc_function_signature();
+		assert_eq!(unsafe { signature.scan_module("libc.so.6") }, Ok(function));
+	}
+
+	#[test]
+	fn test_scan_module_segment_flags() {
+		let (function, signature) = libc_function_signature();
 
-		let mut signature_offset = 0;
-		let mut result: Option<*mut u8> = None;
+		let found = unsafe { signature.scan_module_segments("libc.so.6", Some(SegmentFlags::RX)) }.unwrap();
+		assert_eq!(found.ptr, function);
+		assert_eq!(found.segment.flags, SegmentFlags::RX);
+		assert!(found.segment.address <= function as usize && function as usize - found.segment.address < found.segment.len);
+
+		assert_eq!(
+			unsafe { signature.scan_module_segments("libc.so.6", Some(SegmentFlags::RW)) }.unwrap_err(),
+			ModuleSigScanError::NotFound {
+				module: "libc.so.6".to_string()
+			}
+		);
+	}
+
+	#[test]
+	fn test_modules() {
+		let modules = crate::modules();
+		let libc = modules.iter().find(|module| module.name == "libc.so.6").unwrap();
+		assert!(libc.path.ends_with("libc.so.6"));
+		assert!(libc.segments.iter().any(|segment| segment.flags == SegmentFlags::RX));
+		assert!(libc.segments.iter().all(|segment| segment.address >= libc.base));
+
+		let function = libc::dl_iterate_phdr as *const () as usize;
+		let start = libc.segments.iter().map(|segment| segment.address).min().unwrap();
+		assert!(function >= start && function < start + libc.size);
+
+		let exe = std::env::current_exe().unwrap();
+		assert_eq!(modules[0].path, exe);
+	}
 
+	#[test]
+	fn test_scan_module_selectors() {
+		let (function, signature) = libc_function_signature();
 		unsafe {
-			while data_current <= data_end {
-				if signature[signature_offset] == None
-					|| signature[signature_offset] == Some(*data_current)
-				{
-					if signature.len() <= signature_offset + 1 {
-						if result.is_some() {
-							// Found two matches.
-							return Err(ModuleSigScanError::MultipleFound);
-						}
-						result = Some(data_current.offset(-(signature_offset as isize)));
-						data_current = data_current.offset(-(signature_offset as isize));
-						signature_offset = 0;
-					} else {
-						signature_offset += 1;
-					}
-				} else {
-					data_current = data_current.offset(-(signature_offset as isize));
-					signature_offset = 0;
+			assert_eq!(signature.scan_module(ModuleSelector::Glob("libc.so*".to_string())), Ok(function));
+			assert_eq!(signature.scan_module("c.so.6").unwrap_err(), ModuleSigScanErrorKind::InvalidModule);
+			assert_eq!(signature.scan_module("libc.so").unwrap_err(), ModuleSigScanErrorKind::InvalidModule);
+			match signature.scan_module(ModuleSelector::Glob("*.so*".to_string())).unwrap_err() {
+				ModuleSigScanError::AmbiguousModule { module, matches } => {
+					assert_eq!(module, "*.so*");
+					assert!(matches.iter().any(|path| path.ends_with("libc.so.6")));
 				}
-
-				data_current = data_current.offset(1);
+				err => panic!("{}", err),
 			}
 		}
+	}
+
+	#[test]
+	fn test_module_handle() {
+		let (function, signature) = libc_function_signature();
+		let libc = Module::resolve("libc.so.6").unwrap();
+		assert_eq!(libc.info().name, "libc.so.6");
+		unsafe {
+			assert_eq!(libc.scan(&signature), Some(function));
+			assert_eq!(libc.scan_all(&signature).collect::<Vec<_>>(), vec![function]);
+			assert_eq!(libc.scan_unique(&signature), Ok(function));
+
+			// The first few bytes of a function are bound to appear more than once
+			let prologue = Signature::from(&signature[..1]);
+			assert!(libc.scan_all(&prologue).count() > 1);
+			assert_eq!(libc.scan(&prologue), libc.scan_all(&prologue).next());
+			assert_eq!(
+				libc.scan_unique(&prologue).unwrap_err(),
+				ModuleSigScanError::MultipleFound {
+					module: "libc.so.6".to_string(),
+					addresses: libc.scan_all(&prologue).map(|ptr| ptr as usize).collect()
+				}
+			);
+		}
 
-		result.ok_or(ModuleSigScanError::NotFound)
+		let err = Module::resolve("libc.so").unwrap_err();
+		assert_eq!(err, ModuleSigScanErrorKind::InvalidModule);
+		assert_eq!(err.module(), "libc.so");
+		assert_eq!(err.to_string(), "module `libc.so` is not loaded");
 	}
-}
 
-#[cfg(test)]
-mod tests {}
\ No newline at end of file
+	#[test]
+	fn test_scan_module_captures() {
+		let (function, signature) = libc_function_signature();
+		// Capture the middle of the function instead of matching it
+		let mut captured = Signature::from(&signature[..8]);
+		captured.push(SigElement::Capture { name: "middle".to_string(), len: 32 });
+		captured.extend_from_slice(&signature[40..]);
+
+		let found = unsafe { captured.scan_module_match("libc.so.6") }.unwrap();
+		assert_eq!(found.address(), function as usize);
+		assert_eq!(found.len(), 48);
+		let middle = found.capture("middle").unwrap();
+		assert_eq!(middle.address(), function as usize + 8);
+		assert_eq!(middle.bytes, unsafe { std::slice::from_raw_parts(function.add(8), 32) });
+
+		let libc = Module::resolve("libc.so.6").unwrap();
+		let prologue = Signature::from(&signature[..1]);
+		assert_eq!(unsafe { libc.scan_unique_match(&prologue) }.unwrap_err(), ModuleSigScanErrorKind::MultipleFound);
+	}
+
+	#[test]
+	fn test_scan_module_address() {
+		let (function, signature) = libc_function_signature();
+		let address = unsafe { signature.scan_module_address("libc.so.6") }.unwrap();
+		assert_eq!(address.as_ptr::<u8>(), function);
+		assert_eq!(address.deref::<[u8; 4]>().unwrap(), unsafe { function.cast::<[u8; 4]>().read() });
+		assert_eq!(address.clone().add(8).unwrap().get(), function as usize + 8);
+
+		// The address can't be followed outside of libc's readable segments
+		let libc = Module::resolve("libc.so.6").unwrap();
+		assert!(address.sub(function as usize - libc.info().segments[0].address + 1).is_err());
+		assert!(unsafe { libc.address(0) }.is_err());
+	}
+
+	#[test]
+	fn test_generate_module_signature() {
+		let libc = Module::resolve("libc.so.6").unwrap();
+		for function in [libc::dl_iterate_phdr as *const u8, libc::qsort as *const u8, libc::getenv as *const u8].iter() {
+			let signature = unsafe { SignatureGenerator::new().generate_module(&libc, *function as usize) }.unwrap();
+			assert_eq!(unsafe { libc.scan_unique(&signature) }.unwrap() as usize, *function as usize);
+		}
+		assert_eq!(unsafe { SignatureGenerator::new().generate_module(&libc, 0) }, Err(GenerateError::OutOfBounds { target: 0 }));
+	}
+
+	#[test]
+	fn test_scan_elf_file() {
+		let (function, signature) = libc_function_signature();
+		let libc = Module::resolve("libc.so.6").unwrap();
+		let file = std::fs::read(&libc.info().path).unwrap();
+		let image = Image::parse(&file).unwrap();
+
+		// The file is found at the same address as in memory, less the load bias
+		let found = image.scan_unique_section(".text", &signature).unwrap();
+		assert_eq!(found.address as usize + libc.info().base, function as usize);
+		assert_eq!(image.scan_unique(&signature), Ok(found));
+		assert_eq!(&file[found.offset.unwrap() as usize..][..48], unsafe { std::slice::from_raw_parts(function, 48) });
+	}
+
+	#[test]
+	fn test_parallel_scan_module() {
+		let (function, signature) = libc_function_signature();
+		let libc = Module::resolve("libc.so.6").unwrap();
+		let scanner = ParallelScanner::new().threads(4).shard_size(64 << 10);
+		assert_eq!(unsafe { scanner.scan_module_unique(&libc, &signature) }, Ok(function));
+
+		// Found in many shards, and reported the same way as a serial scan
+		let common: Signature = "48 89 ? 24".parse().unwrap();
+		let serial: Vec<*mut u8> = unsafe { libc.scan_all(&common) }.collect();
+		assert!(serial.len() > 100);
+		assert_eq!(unsafe { scanner.scan_module_all(&libc, &common) }, serial);
+		assert_eq!(unsafe { scanner.scan_module_unique(&libc, &common) }, unsafe { libc.scan_unique(&common) });
+	}
+
+	#[test]
+	fn test_non_utf8_module_names() {
+		use std::ffi::OsStr;
+		use std::os::unix::ffi::OsStrExt;
+
+		let path = std::path::Path::new(OsStr::from_bytes(b"/opt/g\xFFme/lib\xC3\xA9client.so"));
+		assert!(ModuleSelector::Prefix("/opt/".to_string()).matches(path));
+		assert!(ModuleSelector::Suffix("client.so".to_string()).matches(path));
+		assert!(ModuleSelector::Glob("lib?client.so".to_string()).matches(path));
+		assert!(ModuleSelector::Name("lib\u{e9}client.so".to_string()).matches(path));
+		assert!(!ModuleSelector::Name("client.so".to_string()).matches(path));
+
+		assert_eq!(Module::resolve("libc\0.so.6").unwrap_err(), ModuleSigScanErrorKind::InvalidModuleName);
+	}
+}
\ No newline at end of file
@@ -21,18 +21,112 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::address::{Address, Region};
+use crate::signatures::unique;
+use crate::{ResolveError, SigMatch, Signature};
+
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+mod selector;
+pub use selector::ModuleSelector;
+
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "linux")]
-pub use linux::Scanner;
+pub use linux::modules;
 
 #[cfg(target_os = "windows")]
 mod windows;
 #[cfg(target_os = "windows")]
-pub use windows::Scanner;
+pub use windows::modules;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// An error scanning a loaded module
+///
+/// Every variant carries the module that was requested, as the pattern of its [`ModuleSelector`].
+///
+/// Code written against the old fieldless variants can compare [`ModuleSigScanError::kind`] instead, e.g. `err.kind() == ModuleSigScanErrorKind::NotFound`,
+/// or compare the error itself with a [`ModuleSigScanErrorKind`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ModuleSigScanError {
+	/// Failed to find the signature
+	NotFound { module: String },
+
+	/// Found multiple occurrences of the signature, at these addresses
+	MultipleFound { module: String, addresses: Vec<usize> },
+
+	/// Unable to open the specified module
+	InvalidModule { module: String },
+
+	/// The module selector matched more than one loaded module, at these paths
+	AmbiguousModule { module: String, matches: Vec<PathBuf> },
+
+	/// The module name contains a nul byte, so it can't match any loaded module
+	InvalidModuleName { module: String },
+
+	/// The module was found but has no loadable segments to scan (e.g. `PT_LOAD` on Linux)
+	NoLoadableSegments { module: String },
+}
+impl ModuleSigScanError {
+	/// Returns the kind of error, without its context
+	pub fn kind(&self) -> ModuleSigScanErrorKind {
+		match self {
+			ModuleSigScanError::NotFound { .. } => ModuleSigScanErrorKind::NotFound,
+			ModuleSigScanError::MultipleFound { .. } => ModuleSigScanErrorKind::MultipleFound,
+			ModuleSigScanError::InvalidModule { .. } => ModuleSigScanErrorKind::InvalidModule,
+			ModuleSigScanError::AmbiguousModule { .. } => ModuleSigScanErrorKind::AmbiguousModule,
+			ModuleSigScanError::InvalidModuleName { .. } => ModuleSigScanErrorKind::InvalidModuleName,
+			ModuleSigScanError::NoLoadableSegments { .. } => ModuleSigScanErrorKind::NoLoadableSegments,
+		}
+	}
+
+	/// Returns the module that was requested
+	pub fn module(&self) -> &str {
+		match self {
+			ModuleSigScanError::NotFound { module }
+			| ModuleSigScanError::MultipleFound { module, .. }
+			| ModuleSigScanError::InvalidModule { module }
+			| ModuleSigScanError::AmbiguousModule { module, .. }
+			| ModuleSigScanError::InvalidModuleName { module }
+			| ModuleSigScanError::NoLoadableSegments { module } => module,
+		}
+	}
+}
+impl PartialEq<ModuleSigScanErrorKind> for ModuleSigScanError {
+	#[inline]
+	fn eq(&self, kind: &ModuleSigScanErrorKind) -> bool {
+		self.kind() == *kind
+	}
+}
+impl std::fmt::Display for ModuleSigScanError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ModuleSigScanError::NotFound { module } => write!(f, "signature not found in module `{}`", module),
+			ModuleSigScanError::MultipleFound { module, addresses } => {
+				write!(f, "signature found {} times in module `{}`, at", addresses.len(), module)?;
+				for (i, address) in addresses.iter().enumerate() {
+					write!(f, "{} {:#x}", if i == 0 { "" } else { "," }, address)?;
+				}
+				Ok(())
+			}
+			ModuleSigScanError::InvalidModule { module } => write!(f, "module `{}` is not loaded", module),
+			ModuleSigScanError::AmbiguousModule { module, matches } => {
+				write!(f, "module `{}` matched {} loaded modules:", module, matches.len())?;
+				for (i, path) in matches.iter().enumerate() {
+					write!(f, "{} {}", if i == 0 { "" } else { "," }, path.display())?;
+				}
+				Ok(())
+			}
+			ModuleSigScanError::InvalidModuleName { module } => write!(f, "module name `{}` contains a nul byte", module.escape_debug()),
+			ModuleSigScanError::NoLoadableSegments { module } => write!(f, "module `{}` has no loadable segments", module),
+		}
+	}
+}
+impl std::error::Error for ModuleSigScanError {}
+
+/// The kind of a [`ModuleSigScanError`], without its context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ModuleSigScanErrorKind {
 	/// Failed to find the signature
 	NotFound,
 
@@ -41,4 +135,244 @@ pub enum ModuleSigScanError {
 
 	/// Unable to open the specified module
 	InvalidModule,
+
+	/// The module selector matched more than one loaded module
+	AmbiguousModule,
+
+	/// The module name contains a nul byte, so it can't match any loaded module
+	InvalidModuleName,
+
+	/// The module was found but has no loadable segments to scan (e.g. `PT_LOAD` on Linux)
+	NoLoadableSegments,
+}
+
+/// Memory protection of a module's segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SegmentFlags(u8);
+impl SegmentFlags {
+	pub const NONE: SegmentFlags = SegmentFlags(0);
+	pub const R: SegmentFlags = SegmentFlags(0b100);
+	pub const W: SegmentFlags = SegmentFlags(0b010);
+	pub const X: SegmentFlags = SegmentFlags(0b001);
+	pub const RW: SegmentFlags = SegmentFlags(0b110);
+	pub const RX: SegmentFlags = SegmentFlags(0b101);
+	pub const RWX: SegmentFlags = SegmentFlags(0b111);
+
+	/// Returns whether every flag in `other` is also set in `self`
+	#[inline]
+	pub fn contains(self, other: SegmentFlags) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+impl std::ops::BitOr for SegmentFlags {
+	type Output = Self;
+
+	#[inline]
+	fn bitor(self, rhs: Self) -> Self {
+		SegmentFlags(self.0 | rhs.0)
+	}
+}
+
+/// A contiguous, loaded region of a module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Segment {
+	/// The address of the first byte of the segment
+	pub address: usize,
+
+	/// The size of the segment in memory
+	pub len: usize,
+
+	/// The memory protection of the segment
+	pub flags: SegmentFlags,
+}
+impl Segment {
+	/// Returns the memory of this segment
+	///
+	/// The segment must be readable and still loaded
+	#[inline]
+	pub unsafe fn as_slice(&self) -> &'static [u8] {
+		std::slice::from_raw_parts(self.address as *const u8, self.len)
+	}
+}
+
+/// A signature found in a loaded module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleMatch {
+	/// The first byte of the signature
+	pub ptr: *mut u8,
+
+	/// The segment the signature was found in
+	pub segment: Segment,
+}
+
+/// A module loaded in this process
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModuleInfo {
+	/// The file name of the module
+	pub name: OsString,
+
+	/// The path of the module, as reported by the dynamic loader
+	pub path: PathBuf,
+
+	/// The address the module was loaded at
+	///
+	/// On Linux this is the load bias that segment addresses are relative to, on Windows it is the module's handle.
+	pub base: usize,
+
+	/// The loaded segments of the module
+	pub segments: Vec<Segment>,
+
+	/// The size of the module in memory, from the start of its first segment to the end of its last
+	pub size: usize,
+}
+
+/// A handle to a loaded module, resolved once so that it can be scanned repeatedly without looking it up again
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Module {
+	selector: ModuleSelector,
+	info: ModuleInfo,
+}
+
+impl Module {
+	/// Finds a loaded module by its exact path or file name, or by any [`ModuleSelector`]
+	pub fn resolve<S: Into<ModuleSelector>>(module: S) -> Result<Module, ModuleSigScanError> {
+		let selector = module.into();
+		let requested = || selector.pattern().to_string();
+		if selector.pattern().contains('\0') {
+			return Err(ModuleSigScanError::InvalidModuleName { module: requested() });
+		}
+
+		let mut found: Vec<ModuleInfo> = modules().into_iter().filter(|module| selector.matches(&module.path)).collect();
+		if found.len() > 1 {
+			return Err(ModuleSigScanError::AmbiguousModule {
+				module: requested(),
+				matches: found.into_iter().map(|module| module.path).collect(),
+			});
+		}
+		let info = found.pop().ok_or_else(|| ModuleSigScanError::InvalidModule { module: requested() })?;
+		if info.segments.is_empty() {
+			return Err(ModuleSigScanError::NoLoadableSegments { module: requested() });
+		}
+		Ok(Module { selector, info })
+	}
+
+	/// Returns the module's name, path and segments
+	#[inline]
+	pub fn info(&self) -> &ModuleInfo {
+		&self.info
+	}
+
+	/// Returns the selector this module was resolved with
+	#[inline]
+	pub fn selector(&self) -> &ModuleSelector {
+		&self.selector
+	}
+
+	/// Returns the readable segments of the module, optionally restricted to segments with exactly the given flags
+	pub(crate) fn readable_segments(&self, flags: Option<SegmentFlags>) -> impl Iterator<Item = Segment> + '_ {
+		self.info
+			.segments
+			.iter()
+			.filter(move |segment| segment.flags.contains(SegmentFlags::R) && flags.map(|flags| segment.flags == flags).unwrap_or(true))
+			.copied()
+	}
+
+	/// Scans every readable segment of the module, returning the first occurrence of the signature
+	///
+	/// The module must still be loaded
+	pub unsafe fn scan(&self, signature: &Signature) -> Option<*mut u8> {
+		self.scan_all(signature).next()
+	}
+
+	/// Scans every readable segment of the module, returning every occurrence of the signature in ascending order within each segment
+	///
+	/// The module must still be loaded
+	pub unsafe fn scan_all<'a>(&'a self, signature: &'a Signature) -> impl Iterator<Item = *mut u8> + 'a {
+		self.readable_segments(None).flat_map(move |segment| {
+			let memory = segment.as_slice();
+			signature.scan_all(memory).map(move |pos| memory[pos..].as_ptr() as *mut u8)
+		})
+	}
+
+	/// Scans every readable segment of the module for exactly one occurrence of the signature
+	///
+	/// The module must still be loaded
+	#[inline]
+	pub unsafe fn scan_unique(&self, signature: &Signature) -> Result<*mut u8, ModuleSigScanError> {
+		self.scan_unique_segments(signature, None).map(|found| found.ptr)
+	}
+
+	/// Scans the readable segments of the module for exactly one occurrence of the signature, reporting which segment it was found in
+	///
+	/// If `flags` is given, only segments with exactly those flags are scanned (e.g. `SegmentFlags::RX` for code)
+	///
+	/// The module must still be loaded
+	pub unsafe fn scan_unique_segments(&self, signature: &Signature, flags: Option<SegmentFlags>) -> Result<ModuleMatch, ModuleSigScanError> {
+		let matches = self.readable_segments(flags).flat_map(|segment| {
+			let memory = segment.as_slice();
+			signature.scan_all(memory).map(move |pos| ModuleMatch {
+				ptr: memory[pos..].as_ptr() as *mut u8,
+				segment,
+			})
+		});
+		unique(matches).map_err(|found| self.unique_error(found.into_iter().map(|found| found.ptr as usize).collect()))
+	}
+
+	/// Scans every readable segment of the module for exactly one occurrence of the signature, returning the match with its captures
+	///
+	/// Offsets in the match are relative to the segment it was found in, [`SigMatch::address`] and [`Capture::address`](crate::Capture::address) give addresses.
+	///
+	/// The module must still be loaded
+	pub unsafe fn scan_unique_match<'a>(&self, signature: &'a Signature) -> Result<SigMatch<'a>, ModuleSigScanError> {
+		let matches = self.readable_segments(None).flat_map(|segment| signature.scan_all(segment.as_slice()).matches());
+		unique(matches).map_err(|found| self.unique_error(found.iter().map(SigMatch::address).collect()))
+	}
+
+	/// Scans every readable segment of the module for exactly one occurrence of the signature, returning an [`Address`] that can be followed within them
+	///
+	/// The module must stay loaded for as long as the address is used
+	#[inline]
+	pub unsafe fn scan_unique_address(&self, signature: &Signature) -> Result<Address<'static>, ResolveError> {
+		let ptr = self.scan_unique(signature)?;
+		self.address(ptr as usize)
+	}
+
+	/// Returns an [`Address`] that can be followed within the module's readable segments, e.g. for the address of a [`Capture`](crate::Capture)
+	///
+	/// The module must stay loaded for as long as the address is used
+	pub unsafe fn address(&self, address: usize) -> Result<Address<'static>, ResolveError> {
+		let regions = self
+			.readable_segments(None)
+			.map(|segment| Region {
+				address: segment.address,
+				bytes: segment.as_slice(),
+			})
+			.collect();
+		Address::new(address, regions)
+	}
+
+	#[inline]
+	pub(crate) fn not_found(&self) -> ModuleSigScanError {
+		ModuleSigScanError::NotFound {
+			module: self.selector.pattern().to_string(),
+		}
+	}
+
+	#[inline]
+	pub(crate) fn multiple_found(&self, addresses: Vec<usize>) -> ModuleSigScanError {
+		ModuleSigScanError::MultipleFound {
+			module: self.selector.pattern().to_string(),
+			addresses,
+		}
+	}
+
+	/// The error for a signature that was found at these addresses, which isn't exactly once
+	#[inline]
+	pub(crate) fn unique_error(&self, addresses: Vec<usize>) -> ModuleSigScanError {
+		if addresses.is_empty() {
+			self.not_found()
+		} else {
+			self.multiple_found(addresses)
+		}
+	}
 }
\ No newline at end of file
@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Selects a loaded module by its path or file name
+///
+/// Patterns given to [`ModuleSelector::Prefix`], [`ModuleSelector::Suffix`] and [`ModuleSelector::Glob`] are matched against the module's
+/// full path if they contain a path separator, and against its file name otherwise.
+///
+/// Paths are compared byte-wise, so modules with paths that aren't valid UTF-8 can still be selected by the parts that are.
+/// On Windows, all comparisons are ASCII case-insensitive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ModuleSelector {
+	/// The exact path of the module, as reported by the dynamic loader
+	Path(String),
+
+	/// The exact file name of the module, e.g. `client.so`
+	///
+	/// On Windows, `.dll` is appended to a name without an extension, like `GetModuleHandle` does, so `client` selects `client.dll`.
+	Name(String),
+
+	/// The module's file name or path starts with this
+	Prefix(String),
+
+	/// The module's file name or path ends with this
+	Suffix(String),
+
+	/// The module's file name or path matches this glob, where `*` matches any run of characters and `?` matches any single character
+	Glob(String),
+}
+impl ModuleSelector {
+	/// Returns the pattern of this selector
+	#[inline]
+	pub fn pattern(&self) -> &str {
+		match self {
+			ModuleSelector::Path(pattern)
+			| ModuleSelector::Name(pattern)
+			| ModuleSelector::Prefix(pattern)
+			| ModuleSelector::Suffix(pattern)
+			| ModuleSelector::Glob(pattern) => pattern,
+		}
+	}
+
+	/// Returns whether a module at the given path is selected
+	pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+		let path = path_bytes(path.as_ref());
+		let path = &*path;
+		let pattern = self.pattern().as_bytes();
+		match self {
+			ModuleSelector::Path(_) => eq(path, pattern),
+			ModuleSelector::Name(_) => name_matches(file_name(path), pattern),
+			ModuleSelector::Prefix(_) => {
+				let subject = subject(path, pattern);
+				subject.len() >= pattern.len() && eq(&subject[..pattern.len()], pattern)
+			}
+			ModuleSelector::Suffix(_) => {
+				let subject = subject(path, pattern);
+				subject.len() >= pattern.len() && eq(&subject[subject.len() - pattern.len()..], pattern)
+			}
+			ModuleSelector::Glob(_) => glob(pattern, subject(path, pattern)),
+		}
+	}
+}
+impl From<&str> for ModuleSelector {
+	/// Selects the module with this exact path if it contains a path separator, otherwise the module with this exact file name
+	fn from(module: &str) -> Self {
+		if module.bytes().any(is_separator) {
+			ModuleSelector::Path(module.to_string())
+		} else {
+			ModuleSelector::Name(module.to_string())
+		}
+	}
+}
+impl From<String> for ModuleSelector {
+	#[inline]
+	fn from(module: String) -> Self {
+		ModuleSelector::from(module.as_str())
+	}
+}
+impl From<&String> for ModuleSelector {
+	#[inline]
+	fn from(module: &String) -> Self {
+		ModuleSelector::from(module.as_str())
+	}
+}
+impl From<Cow<'_, str>> for ModuleSelector {
+	#[inline]
+	fn from(module: Cow<'_, str>) -> Self {
+		ModuleSelector::from(&*module)
+	}
+}
+impl From<Box<str>> for ModuleSelector {
+	#[inline]
+	fn from(module: Box<str>) -> Self {
+		ModuleSelector::from(&*module)
+	}
+}
+impl From<&ModuleSelector> for ModuleSelector {
+	#[inline]
+	fn from(selector: &ModuleSelector) -> Self {
+		selector.clone()
+	}
+}
+
+#[cfg(unix)]
+#[inline]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+	use std::os::unix::ffi::OsStrExt;
+	Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+#[inline]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+	match path.to_string_lossy() {
+		Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+		Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+	}
+}
+
+#[inline]
+fn is_separator(c: u8) -> bool {
+	c == b'/' || (cfg!(windows) && c == b'\\')
+}
+
+#[inline]
+fn file_name(path: &[u8]) -> &[u8] {
+	path.rsplit(|c| is_separator(*c)).next().unwrap_or(path)
+}
+
+/// The part of the path a pattern should be matched against
+#[inline]
+fn subject<'a>(path: &'a [u8], pattern: &[u8]) -> &'a [u8] {
+	if pattern.iter().copied().any(is_separator) {
+		path
+	} else {
+		file_name(path)
+	}
+}
+
+/// Compares a file name, which on Windows is looked up like `GetModuleHandle` does: `.dll` is appended to a name without an
+/// extension, and a trailing `.` means the file has no extension
+fn name_matches(name: &[u8], pattern: &[u8]) -> bool {
+	if eq(name, pattern) {
+		return true;
+	}
+	if !cfg!(windows) {
+		return false;
+	}
+	match pattern.split_last() {
+		Some((b'.', pattern)) => eq(name, pattern),
+		_ if !pattern.contains(&b'.') => name.len() == pattern.len() + 4 && eq(&name[..pattern.len()], pattern) && eq(&name[pattern.len()..], b".dll"),
+		_ => false,
+	}
+}
+
+#[inline]
+fn eq(a: &[u8], b: &[u8]) -> bool {
+	if cfg!(windows) {
+		a.eq_ignore_ascii_case(b)
+	} else {
+		a == b
+	}
+}
+
+/// The number of bytes in the UTF-8 sequence starting with this byte, or 1 if it isn't the start of a sequence
+#[inline]
+fn utf8_len(first: u8) -> usize {
+	match first {
+		0xF0..=0xF7 => 4,
+		0xE0..=0xEF => 3,
+		0xC0..=0xDF => 2,
+		_ => 1,
+	}
+}
+
+/// Iterative glob matching, backtracking to the most recent `*` on a mismatch
+fn glob(pattern: &[u8], subject: &[u8]) -> bool {
+	let (mut p, mut s) = (0, 0);
+	let mut star: Option<(usize, usize)> = None;
+	while s < subject.len() {
+		match pattern.get(p) {
+			Some(b'*') => {
+				star = Some((p, s));
+				p += 1;
+			}
+			Some(b'?') => {
+				p += 1;
+				s = (s + utf8_len(subject[s])).min(subject.len());
+			}
+			Some(c) if eq(&[*c], &subject[s..s + 1]) => {
+				p += 1;
+				s += 1;
+			}
+			_ => match star {
+				Some((star_p, star_s)) => {
+					p = star_p + 1;
+					s = star_s + 1;
+					star = Some((star_p, star_s + 1));
+				}
+				None => return false,
+			},
+		}
+	}
+	pattern[p..].iter().all(|c| *c == b'*')
+}
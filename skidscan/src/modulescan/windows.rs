@@ -1,101 +1,192 @@
-use crate::ModuleSigScanError;
+use crate::{ModuleInfo, Segment, SegmentFlags};
 
+use std::ffi::{OsStr, OsString};
 use std::mem;
+use std::os::windows::ffi::OsStringExt;
+use std::path::PathBuf;
 use std::ptr;
 
 use winapi::shared::minwindef;
-use winapi::um::libloaderapi;
+use winapi::um::memoryapi;
 use winapi::um::processthreadsapi;
 use winapi::um::psapi;
+use winapi::um::winnt;
 
-pub struct Scanner {
-	_module: minwindef::HMODULE,
-	data_begin: *mut u8,
-	data_end: *mut u8,
-}
+/// Returns the handle and path of every module loaded in this process
+fn loaded_modules() -> Vec<(minwindef::HMODULE, PathBuf)> {
+	unsafe {
+		let process = processthreadsapi::GetCurrentProcess();
 
-impl Scanner {
-	pub fn for_module(name: &str) -> Option<Scanner> {
-		let mut module: minwindef::HMODULE = ptr::null_mut();
-		let data_begin: *mut u8;
-		let data_end: *mut u8;
+		let mut needed: minwindef::DWORD = 0;
+		if psapi::EnumProcessModules(process, ptr::null_mut(), 0, &mut needed) == 0 {
+			return Vec::new();
+		}
 
-		// Construct a null-terminated UTF-16 string to pass to the Windows API
-		let name_winapi: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+		let mut modules: Vec<minwindef::HMODULE> = vec![ptr::null_mut(); needed as usize / mem::size_of::<minwindef::HMODULE>()];
+		if psapi::EnumProcessModules(
+			process,
+			modules.as_mut_ptr(),
+			(modules.len() * mem::size_of::<minwindef::HMODULE>()) as minwindef::DWORD,
+			&mut needed,
+		) == 0
+		{
+			return Vec::new();
+		}
+		// Modules may have been unloaded in between the two calls
+		modules.truncate(needed as usize / mem::size_of::<minwindef::HMODULE>());
 
-		unsafe {
-			if libloaderapi::GetModuleHandleExW(0, name_winapi.as_ptr(), &mut module) == 0 {
-				return None;
-			}
+		let mut path = vec![0u16; 32768];
+		modules
+			.into_iter()
+			.filter_map(|module| {
+				let len = psapi::GetModuleFileNameExW(process, module, path.as_mut_ptr(), path.len() as minwindef::DWORD);
+				if len == 0 {
+					None
+				} else {
+					Some((module, PathBuf::from(OsString::from_wide(&path[..len as usize]))))
+				}
+			})
+			.collect()
+	}
+}
 
-			let mut module_info_wrapper = mem::MaybeUninit::<psapi::MODULEINFO>::zeroed();
-			if psapi::GetModuleInformation(
-				processthreadsapi::GetCurrentProcess(),
-				module,
-				module_info_wrapper.as_mut_ptr(),
-				mem::size_of::<psapi::MODULEINFO>() as u32,
+/// Walks the committed memory regions of a module's image
+fn segments(begin: usize, len: usize) -> Vec<Segment> {
+	let mut segments = Vec::new();
+	let end = begin + len;
+	let mut address = begin;
+	while address < end {
+		let mut info = mem::MaybeUninit::<winnt::MEMORY_BASIC_INFORMATION>::zeroed();
+		let info = unsafe {
+			if memoryapi::VirtualQuery(
+				address as *const _,
+				info.as_mut_ptr(),
+				mem::size_of::<winnt::MEMORY_BASIC_INFORMATION>(),
 			) == 0
 			{
-				libloaderapi::FreeLibrary(module);
-				return None;
+				break;
 			}
+			info.assume_init()
+		};
 
-			let module_info = module_info_wrapper.assume_init();
-			data_begin = module_info.lpBaseOfDll as *mut u8;
-			data_end = data_begin
-				.offset(module_info.SizeOfImage as isize)
-				.offset(-1);
+		let region_end = (info.BaseAddress as usize + info.RegionSize).min(end);
+		if info.State == winnt::MEM_COMMIT && info.Protect & winnt::PAGE_GUARD == 0 {
+			let flags = match info.Protect & 0xFF {
+				winnt::PAGE_READONLY => SegmentFlags::R,
+				winnt::PAGE_READWRITE | winnt::PAGE_WRITECOPY => SegmentFlags::RW,
+				winnt::PAGE_EXECUTE => SegmentFlags::X,
+				winnt::PAGE_EXECUTE_READ => SegmentFlags::RX,
+				winnt::PAGE_EXECUTE_READWRITE | winnt::PAGE_EXECUTE_WRITECOPY => SegmentFlags::RWX,
+				_ => SegmentFlags::NONE,
+			};
+			segments.push(Segment {
+				address,
+				len: region_end - address,
+				flags,
+			});
 		}
-
-		Some(Scanner {
-			_module: module,
-			data_begin,
-			data_end,
-		})
+		address = region_end;
 	}
 
-	pub fn find(&self, signature: &[Option<u8>]) -> Result<*mut u8, ModuleSigScanError> {
-		let mut data_current = self.data_begin;
-		let data_end = self.data_end;
-		let mut signature_offset = 0;
-		let mut result: Option<*mut u8> = None;
+	segments
+}
 
-		unsafe {
-			while data_current <= data_end {
-				if signature[signature_offset] == None
-					|| signature[signature_offset] == Some(*data_current)
+/// Returns every module loaded in this process, in load order
+pub fn modules() -> Vec<ModuleInfo> {
+	let process = unsafe { processthreadsapi::GetCurrentProcess() };
+	loaded_modules()
+		.into_iter()
+		.filter_map(|(module, path)| {
+			let module_info = unsafe {
+				let mut module_info_wrapper = mem::MaybeUninit::<psapi::MODULEINFO>::zeroed();
+				if psapi::GetModuleInformation(
+					process,
+					module,
+					module_info_wrapper.as_mut_ptr(),
+					mem::size_of::<psapi::MODULEINFO>() as u32,
+				) == 0
 				{
-					if signature.len() <= signature_offset + 1 {
-						if result.is_some() {
-							// Found two matches.
-							return Err(ModuleSigScanError::MultipleFound);
-						}
-						result = Some(data_current.offset(-(signature_offset as isize)));
-						data_current = data_current.offset(-(signature_offset as isize));
-						signature_offset = 0;
-					} else {
-						signature_offset += 1;
-					}
-				} else {
-					data_current = data_current.offset(-(signature_offset as isize));
-					signature_offset = 0;
+					return None;
 				}
+				module_info_wrapper.assume_init()
+			};
 
-				data_current = data_current.offset(1);
-			}
-		}
+			let base = module_info.lpBaseOfDll as usize;
+			let size = module_info.SizeOfImage as usize;
+			Some(ModuleInfo {
+				name: path.file_name().map(OsStr::to_os_string).unwrap_or_default(),
+				path,
+				base,
+				size,
+				segments: segments(base, size),
+			})
+		})
+		.collect()
+}
 
-		result.ok_or(ModuleSigScanError::NotFound)
+#[cfg(test)]
+mod tests {
+	use crate::{Module, ModuleSigScanError, SegmentFlags, Signature};
+
+	use winapi::um::libloaderapi;
+
+	/// Builds a signature from the first bytes of a system call stub in ntdll, which lives in ntdll's executable segment
+	///
+	/// Each stub loads its own system call number, so its first bytes are unique.
+	fn ntdll_function_signature() -> (*mut u8, Signature) {
+		let function = unsafe {
+			let ntdll = libloaderapi::GetModuleHandleA(b"ntdll.dll\0".as_ptr().cast());
+			libloaderapi::GetProcAddress(ntdll, b"NtQueryVirtualMemory\0".as_ptr().cast()) as *mut u8
+		};
+		assert!(!function.is_null());
+		let bytes = unsafe { std::slice::from_raw_parts(function, 24) };
+		(function, Signature::from(bytes))
 	}
-}
 
-impl Drop for Scanner {
-	fn drop(&mut self) {
-		// TODO: WTf this started throwing?!
-		/*
-		unsafe {
-			libloaderapi::FreeLibrary(self.module);
-		}
-		*/
+	#[test]
+	fn test_modules() {
+		let modules = crate::modules();
+		let ntdll = modules.iter().find(|module| module.name.eq_ignore_ascii_case("ntdll.dll")).unwrap();
+		assert!(ntdll.path.is_absolute());
+		assert!(ntdll.segments.iter().any(|segment| segment.flags == SegmentFlags::RX));
+		assert!(ntdll.segments.iter().all(|segment| segment.address >= ntdll.base && segment.address + segment.len <= ntdll.base + ntdll.size));
+
+		let (function, _) = ntdll_function_signature();
+		assert!(function as usize >= ntdll.base && (function as usize) < ntdll.base + ntdll.size);
+
+		let exe = std::env::current_exe().unwrap();
+		assert!(modules[0].path.as_os_str().eq_ignore_ascii_case(exe.as_os_str()));
+	}
+
+	#[test]
+	fn test_scan_module_every_segment() {
+		let (function, signature) = ntdll_function_signature();
+		assert_eq!(unsafe { signature.scan_module("ntdll.dll") }, Ok(function));
+	}
+
+	#[test]
+	fn test_scan_module_without_extension() {
+		let (function, signature) = ntdll_function_signature();
+		assert_eq!(unsafe { signature.scan_module("ntdll") }, Ok(function));
+		assert_eq!(unsafe { signature.scan_module("NTDLL") }, Ok(function));
+		assert_eq!(Module::resolve("ntdll").unwrap().info(), Module::resolve("ntdll.dll").unwrap().info());
+		assert!(Module::resolve("ntdll.").is_err());
+	}
+
+	#[test]
+	fn test_scan_module_segment_flags() {
+		let (function, signature) = ntdll_function_signature();
+
+		let found = unsafe { signature.scan_module_segments("ntdll.dll", Some(SegmentFlags::RX)) }.unwrap();
+		assert_eq!(found.ptr, function);
+		assert_eq!(found.segment.flags, SegmentFlags::RX);
+		assert!(found.segment.address <= function as usize && function as usize - found.segment.address < found.segment.len);
+
+		assert_eq!(
+			unsafe { signature.scan_module_segments("ntdll.dll", Some(SegmentFlags::R)) }.unwrap_err(),
+			ModuleSigScanError::NotFound {
+				module: "ntdll.dll".to_string()
+			}
+		);
 	}
 }
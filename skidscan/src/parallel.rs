@@ -0,0 +1,167 @@
+use crate::signatures::unique;
+use crate::stream::MAX_OVERLAP;
+use crate::{FileScanner, Module, ModuleSigScanError, Signature, UniqueScanError};
+
+use std::io;
+use std::ops::Range;
+
+/// Scans large regions on multiple threads, by splitting them into shards that overlap by the length of the signature minus one
+///
+/// Results are always in the same order as the serial scans, and uniqueness is checked over the whole region, so a signature
+/// that is found in more than one shard is reported as found multiple times just like it would be by a serial scan.
+///
+/// Shards overlap by at most 1 MiB, the same as the chunks of a [`FileScanner`], so a match of a signature with longer gaps is only
+/// found if it extends less than 1 MiB past the end of the shard it starts in.
+///
+/// With the `rayon` feature, shards are scanned on rayon's global thread pool, otherwise on scoped threads spawned for each scan.
+///
+/// ```ignore
+/// let scanner = ParallelScanner::new().shard_size(4 << 20);
+/// let ptr: *mut u8 = unsafe { scanner.scan_module_unique(&module, &signature!("E8 ? ? ? ? 48 85 C0"))? };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParallelScanner {
+	threads: usize,
+	pub(crate) shard_size: usize,
+}
+impl Default for ParallelScanner {
+	#[inline]
+	fn default() -> Self {
+		ParallelScanner::new()
+	}
+}
+impl ParallelScanner {
+	/// A scanner that uses every available core, with 1 MiB shards
+	#[inline]
+	pub fn new() -> ParallelScanner {
+		ParallelScanner {
+			threads: std::thread::available_parallelism().map(usize::from).unwrap_or(1),
+			shard_size: 1 << 20,
+		}
+	}
+
+	/// Sets how many threads are spawned for each scan, which is ignored with the `rayon` feature
+	#[inline]
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.threads = threads.max(1);
+		self
+	}
+
+	/// Sets how many bytes each shard starts matches in, 1 MiB by default
+	#[inline]
+	pub fn shard_size(mut self, shard_size: usize) -> Self {
+		self.shard_size = shard_size.max(1);
+		self
+	}
+
+	/// Scans a slice of bytes, returning every occurrence of the signature in ascending order like [`Signature::scan_all`]
+	pub fn scan_all(&self, signature: &Signature, bytes: &[u8]) -> Vec<usize> {
+		let shards = self.shards(bytes.len());
+		self.run(shards.len(), |i| scan_shard(signature, bytes, shards[i].clone())).concat()
+	}
+
+	/// Scans a slice of bytes for exactly one occurrence of the signature, like [`Signature::scan_unique`]
+	pub fn scan_unique(&self, signature: &Signature, bytes: &[u8]) -> Result<usize, UniqueScanError> {
+		unique(self.scan_all(signature, bytes).into_iter()).map_err(|offsets| if offsets.is_empty() { UniqueScanError::NotFound } else { UniqueScanError::MultipleFound { offsets } })
+	}
+
+	/// Scans every readable segment of a module, returning every occurrence of the signature in ascending order within each segment
+	/// like [`Module::scan_all`]
+	///
+	/// The module must still be loaded
+	pub unsafe fn scan_module_all(&self, module: &Module, signature: &Signature) -> Vec<*mut u8> {
+		self.scan_segments(module, signature).into_iter().map(|address| address as *mut u8).collect()
+	}
+
+	/// Scans every readable segment of a module for exactly one occurrence of the signature, like [`Module::scan_unique`]
+	///
+	/// The module must still be loaded
+	pub unsafe fn scan_module_unique(&self, module: &Module, signature: &Signature) -> Result<*mut u8, ModuleSigScanError> {
+		unique(self.scan_segments(module, signature).into_iter())
+			.map(|address| address as *mut u8)
+			.map_err(|addresses| module.unique_error(addresses))
+	}
+
+	/// Scans a file, returning the offset of every occurrence of the signature in ascending order like [`FileScanner::scan_all`]
+	///
+	/// Each shard is read in chunks of the scanner's chunk size, or the whole file is mapped if the scanner memory maps it.
+	pub fn scan_file_all(&self, file: &FileScanner, signature: &Signature) -> io::Result<Vec<u64>> {
+		file.par_scan_all(signature, self)
+	}
+
+	unsafe fn scan_segments(&self, module: &Module, signature: &Signature) -> Vec<usize> {
+		let shards: Vec<(&[u8], Range<usize>)> = module
+			.readable_segments(None)
+			.flat_map(|segment| {
+				let memory = segment.as_slice();
+				self.shards(memory.len()).into_iter().map(move |shard| (memory, shard))
+			})
+			.collect();
+		self.run(shards.len(), |i| {
+			let (memory, shard) = &shards[i];
+			scan_shard(signature, memory, shard.clone())
+				.into_iter()
+				.map(|offset| memory.as_ptr() as usize + offset)
+				.collect::<Vec<_>>()
+		})
+		.concat()
+	}
+
+	/// Splits `len` bytes into the ranges that each shard starts matches in, with a single empty shard if there are no bytes
+	fn shards(&self, len: usize) -> Vec<Range<usize>> {
+		(0..len.max(1)).step_by(self.shard_size).map(|shard| shard..shard.saturating_add(self.shard_size).min(len)).collect()
+	}
+
+	/// Calls `f` for each shard index on multiple threads, returning the results in order
+	#[cfg(feature = "rayon")]
+	pub(crate) fn run<T: Send, F: Fn(usize) -> T + Sync>(&self, shards: usize, f: F) -> Vec<T> {
+		use rayon::prelude::*;
+		(0..shards).into_par_iter().map(&f).collect()
+	}
+
+	/// Calls `f` for each shard index on multiple threads, returning the results in order
+	#[cfg(not(feature = "rayon"))]
+	pub(crate) fn run<T: Send, F: Fn(usize) -> T + Sync>(&self, shards: usize, f: F) -> Vec<T> {
+		use std::sync::atomic::{AtomicUsize, Ordering};
+
+		let threads = self.threads.min(shards);
+		if threads <= 1 {
+			return (0..shards).map(f).collect();
+		}
+
+		let next = AtomicUsize::new(0);
+		let mut results: Vec<(usize, T)> = std::thread::scope(|scope| {
+			let workers: Vec<_> = (0..threads)
+				.map(|_| {
+					scope.spawn(|| {
+						let mut results = Vec::new();
+						loop {
+							let shard = next.fetch_add(1, Ordering::Relaxed);
+							if shard >= shards {
+								break results;
+							}
+							results.push((shard, f(shard)));
+						}
+					})
+				})
+				.collect();
+			workers
+				.into_iter()
+				.flat_map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+				.collect()
+		});
+		results.sort_unstable_by_key(|(shard, _)| *shard);
+		results.into_iter().map(|(_, result)| result).collect()
+	}
+}
+
+/// Scans for matches that start in `shard`, which may extend past it by the length of the signature minus one
+fn scan_shard(signature: &Signature, bytes: &[u8], shard: Range<usize>) -> Vec<usize> {
+	let overlap = signature.max_len().saturating_sub(1).min(MAX_OVERLAP);
+	let end = shard.end.saturating_add(overlap).min(bytes.len());
+	signature
+		.scan_all(&bytes[shard.start..end])
+		.starting_before(shard.end - shard.start)
+		.map(|offset| shard.start + offset)
+		.collect()
+}
@@ -0,0 +1,466 @@
+use crate::{ModuleSelector, ModuleSigScanError, SegmentFlags, Signature, StreamScanner};
+
+use std::ffi::{c_void, OsStr, OsString};
+use std::fs::File;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+
+/// An error scanning another process
+#[derive(Debug)]
+pub enum ProcessError {
+	/// Reading the process's memory maps or memory failed, e.g. because the process doesn't exist or can't be read
+	Io(io::Error),
+
+	/// Finding the module or the signature failed
+	Scan(ModuleSigScanError),
+}
+impl From<io::Error> for ProcessError {
+	#[inline]
+	fn from(error: io::Error) -> Self {
+		ProcessError::Io(error)
+	}
+}
+impl From<ModuleSigScanError> for ProcessError {
+	#[inline]
+	fn from(error: ModuleSigScanError) -> Self {
+		ProcessError::Scan(error)
+	}
+}
+impl std::fmt::Display for ProcessError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ProcessError::Io(error) => write!(f, "{}", error),
+			ProcessError::Scan(error) => write!(f, "{}", error),
+		}
+	}
+}
+impl std::error::Error for ProcessError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ProcessError::Io(error) => Some(error),
+			ProcessError::Scan(error) => Some(error),
+		}
+	}
+}
+
+/// A mapping in another process's memory, from `/proc/<pid>/maps`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MemoryRegion {
+	/// The address of the first byte of the region in the process
+	pub start: usize,
+
+	/// The address after the last byte of the region in the process
+	pub end: usize,
+
+	/// The memory protection of the region
+	pub flags: SegmentFlags,
+
+	/// The offset of the region in the mapped file
+	pub offset: u64,
+
+	/// The mapped file, or a pseudo path such as `[heap]` or `[stack]`, or `None` for anonymous mappings
+	pub path: Option<PathBuf>,
+}
+impl MemoryRegion {
+	/// The size of the region
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.end - self.start
+	}
+
+	/// Returns whether the region is empty
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.start == self.end
+	}
+
+	/// Parses a line of `/proc/<pid>/maps`, e.g. `7f0000000000-7f0000001000 r-xp 00001000 08:01 1234    /usr/lib/libc.so.6`
+	fn parse(line: &[u8]) -> Option<MemoryRegion> {
+		let mut fields = line.splitn(6, |byte| *byte == b' ');
+		let mut range = fields.next()?.splitn(2, |byte| *byte == b'-');
+		let start = parse_hex(range.next()?)? as usize;
+		let end = parse_hex(range.next()?)? as usize;
+
+		let perms = fields.next()?;
+		let mut flags = SegmentFlags::NONE;
+		for (flag, perm) in [SegmentFlags::R, SegmentFlags::W, SegmentFlags::X].iter().zip(perms) {
+			if *perm != b'-' {
+				flags = flags | *flag;
+			}
+		}
+		let offset = parse_hex(fields.next()?)?;
+
+		// The path is padded with spaces after the device and inode, and may contain spaces itself
+		let path = fields.nth(2).map(|path| &path[path.iter().take_while(|byte| **byte == b' ').count()..]).filter(|path| !path.is_empty());
+		Some(MemoryRegion {
+			start,
+			end,
+			flags,
+			offset,
+			path: path.map(|path| PathBuf::from(OsStr::from_bytes(path))),
+		})
+	}
+}
+
+/// The size of a page of memory, which unreadable memory is skipped by
+fn page_size() -> usize {
+	match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+		size if size > 0 => size as usize,
+		_ => 4096,
+	}
+}
+
+fn parse_hex(digits: &[u8]) -> Option<u64> {
+	u64::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+}
+
+/// A module mapped into another process, made up of every mapping of the same file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProcessModule {
+	/// The file name of the module
+	pub name: OsString,
+
+	/// The path of the module
+	pub path: PathBuf,
+
+	/// The address the module was mapped at, the start of its first mapping
+	pub base: usize,
+
+	/// The mappings of the module, in ascending order
+	pub regions: Vec<MemoryRegion>,
+
+	/// The size of the module in memory, from the start of its first mapping to the end of its last
+	pub size: usize,
+}
+
+/// Another process on Linux, whose memory is read with `process_vm_readv`, or `/proc/<pid>/mem` if that isn't permitted
+///
+/// Memory is read and scanned in chunks, and every address is an address in the other process. Reading another process's memory
+/// needs the same permissions as attaching a debugger to it, e.g. being its parent, or `CAP_SYS_PTRACE`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Process {
+	pid: u32,
+	chunk_size: usize,
+	/// Always read through `/proc/<pid>/mem`
+	proc_mem: bool,
+}
+impl Process {
+	/// A process by its pid, which isn't checked until it is read
+	#[inline]
+	pub fn new(pid: u32) -> Process {
+		Process {
+			pid,
+			chunk_size: 1 << 20,
+			proc_mem: false,
+		}
+	}
+
+	/// This process, read as if it were another one
+	#[inline]
+	pub fn current() -> Process {
+		Process::new(std::process::id())
+	}
+
+	/// The pid of the process
+	#[inline]
+	pub fn pid(&self) -> u32 {
+		self.pid
+	}
+
+	/// Sets how many bytes are read at a time when scanning, 1 MiB by default
+	#[inline]
+	pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+		self.chunk_size = chunk_size.max(1);
+		self
+	}
+
+	/// Returns every mapping in the process, from `/proc/<pid>/maps`
+	pub fn regions(&self) -> io::Result<Vec<MemoryRegion>> {
+		let maps = std::fs::read(format!("/proc/{}/maps", self.pid))?;
+		Ok(maps.split(|byte| *byte == b'\n').filter_map(MemoryRegion::parse).collect())
+	}
+
+	/// Returns every file mapped into the process that looks like a module, by the first time it was mapped
+	pub fn modules(&self) -> io::Result<Vec<ProcessModule>> {
+		let mut modules: Vec<ProcessModule> = Vec::new();
+		for region in self.regions()? {
+			let path = match &region.path {
+				Some(path) if path.is_absolute() => path.clone(),
+				_ => continue,
+			};
+			match modules.iter_mut().find(|module| module.path == path) {
+				Some(module) => {
+					module.size = region.end.max(module.base + module.size) - module.base;
+					module.regions.push(region);
+				}
+				None => modules.push(ProcessModule {
+					name: path.file_name().map(OsStr::to_os_string).unwrap_or_default(),
+					path,
+					base: region.start,
+					size: region.len(),
+					regions: vec![region],
+				}),
+			}
+		}
+		Ok(modules)
+	}
+
+	/// Finds a module mapped into the process by its exact path or file name, or by any [`ModuleSelector`]
+	pub fn module<S: Into<ModuleSelector>>(&self, module: S) -> Result<ProcessModule, ProcessError> {
+		let selector = module.into();
+		let requested = || selector.pattern().to_string();
+		if selector.pattern().contains('\0') {
+			return Err(ModuleSigScanError::InvalidModuleName { module: requested() }.into());
+		}
+
+		let mut found: Vec<ProcessModule> = self.modules()?.into_iter().filter(|module| selector.matches(&module.path)).collect();
+		if found.len() > 1 {
+			return Err(ModuleSigScanError::AmbiguousModule {
+				module: requested(),
+				matches: found.into_iter().map(|module| module.path).collect(),
+			}
+			.into());
+		}
+		found.pop().ok_or_else(|| ModuleSigScanError::InvalidModule { module: requested() }.into())
+	}
+
+	/// Reads the process's memory at `address` into `buf`, returning how many bytes could be read
+	#[inline]
+	pub fn read(&self, address: usize, buf: &mut [u8]) -> io::Result<usize> {
+		Reader::new(self).read(address, buf)
+	}
+
+	/// Scans every readable mapping of the process, returning the address of every occurrence of the signature in ascending order
+	///
+	/// Mappings that can't be read (e.g. `[vvar]`) are skipped, but failing to read the process at all, e.g. because it has exited
+	/// or reading it isn't permitted, is an error.
+	pub fn scan_all(&self, signature: &Signature) -> io::Result<Vec<usize>> {
+		let regions = self.regions()?;
+		self.scan_regions(signature, &regions)
+	}
+
+	/// Scans the readable mappings of a module, returning the address of every occurrence of the signature in ascending order
+	pub fn scan_module_all<S: Into<ModuleSelector>>(&self, signature: &Signature, module: S) -> Result<Vec<usize>, ProcessError> {
+		let module = self.module(module)?;
+		Ok(self.scan_regions(signature, &module.regions)?)
+	}
+
+	/// Scans the readable mappings of a module for exactly one occurrence of the signature, the same as [`Signature::scan_module`]
+	pub fn scan_module<S: Into<ModuleSelector>>(&self, signature: &Signature, module: S) -> Result<usize, ProcessError> {
+		let selector = module.into();
+		let module = || selector.pattern().to_string();
+		let mut found = self.scan_module_all(signature, selector.clone())?;
+		match found.len() {
+			0 => Err(ModuleSigScanError::NotFound { module: module() }.into()),
+			1 => Ok(found.remove(0)),
+			_ => Err(ModuleSigScanError::MultipleFound { module: module(), addresses: found }.into()),
+		}
+	}
+
+	/// Scans the memory between `start` and `end` in chunks, returning the address of every occurrence of the signature in ascending order
+	///
+	/// Pages that aren't mapped are skipped, matches never span them. Any other error reading the process, e.g. because it has exited
+	/// or reading it isn't permitted, is returned. Chunks overlap by at most 1 MiB, the same as a [`StreamScanner`].
+	pub fn scan_range_all(&self, signature: &Signature, start: usize, end: usize) -> io::Result<Vec<usize>> {
+		let mut found = Vec::new();
+		self.scan_range(&mut Reader::new(self), signature, start, end, &mut found)?;
+		Ok(found)
+	}
+
+	/// Scans the readable regions, skipping pages that aren't mapped
+	fn scan_regions(&self, signature: &Signature, regions: &[MemoryRegion]) -> io::Result<Vec<usize>> {
+		let mut reader = Reader::new(self);
+		let mut found = Vec::new();
+		for region in regions.iter().filter(|region| region.flags.contains(SegmentFlags::R)) {
+			self.scan_range(&mut reader, signature, region.start, region.end, &mut found)?;
+		}
+		Ok(found)
+	}
+
+	fn scan_range(&self, reader: &mut Reader, signature: &Signature, start: usize, end: usize, found: &mut Vec<usize>) -> io::Result<()> {
+		let page_size = page_size();
+		let mut scanner = StreamScanner::new(signature);
+		let mut chunk = vec![0; self.chunk_size.min(end.saturating_sub(start))];
+		let mut address = start;
+		let mut report = |offset: u64| found.push(start + offset as usize);
+		while address < end {
+			let len = chunk.len().min(end - address);
+			match reader.read(address, &mut chunk[..len]) {
+				Ok(read) if read > 0 => {
+					scanner.feed_with(&chunk[..read], &mut report);
+					address += read;
+					continue;
+				}
+				// `process_vm_readv` fails with EFAULT and `/proc/<pid>/mem` with EIO on pages that aren't mapped
+				Ok(_) => {}
+				Err(error) if matches!(error.raw_os_error(), Some(libc::EFAULT) | Some(libc::EIO)) => {}
+				Err(error) => return Err(error),
+			}
+
+			// Skip the unreadable page, matches can't span it
+			let skip = (page_size - address % page_size).min(end - address);
+			scanner.finish_with(skip as u64, &mut report);
+			address += skip;
+		}
+		scanner.finish_with(0, &mut report);
+		Ok(())
+	}
+}
+
+/// Reads a process's memory, falling back to `/proc/<pid>/mem` when `process_vm_readv` isn't permitted
+struct Reader {
+	pid: u32,
+	mem: Option<File>,
+	proc_mem: bool,
+}
+impl Reader {
+	fn new(process: &Process) -> Self {
+		Reader {
+			pid: process.pid,
+			mem: None,
+			proc_mem: process.proc_mem,
+		}
+	}
+
+	fn read(&mut self, address: usize, buf: &mut [u8]) -> io::Result<usize> {
+		if !self.proc_mem {
+			let local = libc::iovec {
+				iov_base: buf.as_mut_ptr() as *mut c_void,
+				iov_len: buf.len(),
+			};
+			let remote = libc::iovec {
+				iov_base: address as *mut c_void,
+				iov_len: buf.len(),
+			};
+			let read = unsafe { libc::process_vm_readv(self.pid as libc::pid_t, &local, 1, &remote, 1, 0) };
+			if read >= 0 {
+				return Ok(read as usize);
+			}
+			let error = io::Error::last_os_error();
+			match error.raw_os_error() {
+				Some(libc::ENOSYS) | Some(libc::EPERM) => self.proc_mem = true,
+				_ => return Err(error),
+			}
+		}
+
+		let mem = match &mut self.mem {
+			Some(mem) => mem,
+			None => self.mem.get_or_insert(File::open(format!("/proc/{}/mem", self.pid))?),
+		};
+		mem.read_at(buf, address as u64)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{MemoryRegion, Process, ProcessError};
+	use crate::{ModuleSigScanError, ModuleSigScanErrorKind, SegmentFlags, Signature};
+
+	use std::path::PathBuf;
+
+	#[test]
+	fn test_parse_maps() {
+		let region = MemoryRegion::parse(b"7f0000001000-7f0000003000 r-xp 00001000 08:01 1234                       /opt/my game/libclient.so").unwrap();
+		assert_eq!(
+			region,
+			MemoryRegion {
+				start: 0x7f0000001000,
+				end: 0x7f0000003000,
+				flags: SegmentFlags::RX,
+				offset: 0x1000,
+				path: Some(PathBuf::from("/opt/my game/libclient.so")),
+			}
+		);
+		assert_eq!(region.len(), 0x2000);
+		let anonymous = MemoryRegion::parse(b"7f0000003000-7f0000004000 rw-p 00000000 00:00 0 ").unwrap();
+		assert_eq!((anonymous.flags, anonymous.path), (SegmentFlags::RW, None));
+		let heap = MemoryRegion::parse(b"55550000-55560000 ---p 00000000 00:00 0       [heap]").unwrap();
+		assert_eq!((heap.flags, heap.path), (SegmentFlags::NONE, Some(PathBuf::from("[heap]"))));
+		assert!(MemoryRegion::parse(b"").is_none());
+		assert!(MemoryRegion::parse(b"7f0000003000 rw-p 00000000 00:00 0").is_none());
+	}
+
+	#[test]
+	fn test_scan_chunk_boundaries() {
+		let mut bytes = vec![0u8; 4096];
+		let marker = [0x5C, 0x1D, 0x5C, 0xA7, 0x00, 0xE7, 0x3B];
+		for offset in [0, 13, 29, 36, 100, 4089].iter() {
+			bytes[*offset..*offset + marker.len()].copy_from_slice(&marker);
+		}
+		let start = bytes.as_ptr() as usize;
+		let signature = Signature::from(&marker[..]);
+		let expected: Vec<usize> = signature.scan_all(&bytes).map(|offset| start + offset).collect();
+		assert_eq!(expected.len(), 6);
+
+		for chunk_size in [1, 3, 7, 16, 4096].iter() {
+			let process = Process::current().chunk_size(*chunk_size);
+			assert_eq!(process.scan_range_all(&signature, start, start + bytes.len()).unwrap(), expected, "chunk size {}", chunk_size);
+
+			let mut process = process;
+			process.proc_mem = true;
+			assert_eq!(process.scan_range_all(&signature, start, start + bytes.len()).unwrap(), expected, "chunk size {}", chunk_size);
+
+			// The first pages are never mapped, they are skipped rather than failing the scan
+			assert_eq!(process.scan_range_all(&signature, 0, 2 * super::page_size()).unwrap(), vec![]);
+			process.proc_mem = false;
+			assert_eq!(process.scan_range_all(&signature, 0, 2 * super::page_size()).unwrap(), vec![]);
+		}
+	}
+
+	#[test]
+	fn test_scan_process_module() {
+		let process = Process::current();
+		let libc = process.module("libc.so.6").unwrap();
+		assert!(libc.regions.iter().any(|region| region.flags == SegmentFlags::RX));
+
+		// The ELF header is at the start of the module's first mapping
+		let mut header = [0; 16];
+		assert_eq!(process.read(libc.base, &mut header).unwrap(), 16);
+		let signature = Signature::from(&header[..]);
+		assert!(process.scan_module_all(&signature, "libc.so.6").unwrap().contains(&libc.base));
+
+		// A function is found at the same address as it is in this process
+		let function = libc::dl_iterate_phdr as *const u8;
+		let signature = Signature::from(unsafe { std::slice::from_raw_parts(function, 48) });
+		assert_eq!(process.scan_module(&signature, "libc.so.6").unwrap(), function as usize);
+
+		match process.scan_module(&Signature::from(&[0x00, 0x00][..]), "libc.so.6") {
+			Err(ProcessError::Scan(ModuleSigScanError::MultipleFound { addresses, .. })) => assert!(addresses.len() > 1),
+			result => panic!("{:?}", result),
+		}
+		match process.scan_module(&signature, "not a module.so") {
+			Err(ProcessError::Scan(error)) => assert_eq!(error, ModuleSigScanErrorKind::InvalidModule),
+			result => panic!("{:?}", result),
+		}
+		assert!(Process::new(u32::MAX).regions().is_err());
+	}
+
+	#[test]
+	fn test_scan_child_process() {
+		let mut child = match std::process::Command::new("sleep").arg("10").spawn() {
+			Ok(child) => child,
+			Err(_) => return,
+		};
+		let process = Process::new(child.id());
+		// The parent can resume before the child's executable is mapped
+		let sleep = (0..100)
+			.find_map(|_| {
+				std::thread::sleep(std::time::Duration::from_millis(10));
+				process.modules().unwrap().into_iter().find(|module| module.name == "sleep")
+			})
+			.unwrap();
+		let regions = process.regions().unwrap();
+		assert!(regions.iter().any(|region| region.path == Some(PathBuf::from("[stack]"))));
+
+		let found = process.scan_module_all(&Signature::from(&b"\x7FELF"[..]), sleep.path.as_path().to_str().unwrap()).unwrap();
+		assert_eq!(found.first(), Some(&sleep.base));
+
+		child.kill().ok();
+		child.wait().ok();
+
+		// A process that has exited can't be read, which isn't the same as the signature not being found
+		assert!(process.scan_range_all(&Signature::from(&b"\x7FELF"[..]), sleep.base, sleep.base + 16).is_err());
+	}
+}
@@ -0,0 +1,275 @@
+use crate::{Module, ModuleSelector, ModuleSigScanError, Signature, matcher};
+
+/// The longest run of fixed bytes used to anchor a signature in the automaton
+///
+/// Longer runs give fewer false positives but grow the automaton.
+const MAX_ANCHOR_LEN: usize = 8;
+
+/// A match reported by a [`SignatureSet`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SetMatch {
+	/// The offset of the first byte of the match
+	pub offset: usize,
+
+	/// The id of the signature that matched, which is its index in the set
+	pub id: usize,
+}
+
+/// The fixed bytes a signature is anchored on, and their offset from the start of a match
+struct Anchor {
+	offset: usize,
+	bytes: Vec<u8>,
+}
+
+/// Many signatures compiled into a single automaton so that they can all be found in one pass over the bytes
+///
+/// Each signature is anchored on a run of its fixed bytes, the anchors are searched for using Aho-Corasick
+/// and every anchor hit is then verified against the whole signature, wildcards included.
+///
+/// Signature ids are their index in the set.
+pub struct SignatureSet {
+	signatures: Vec<Signature>,
+	anchors: Vec<Option<Anchor>>,
+
+	/// Signatures without a single exact byte, which must be checked at every offset
+	unanchored: Vec<usize>,
+
+	/// Dense DFA transitions, `transitions[state * 256 + byte]`
+	transitions: Vec<u32>,
+
+	/// The signature ids whose anchor ends at each state, including those reachable through failure links
+	outputs: Vec<Vec<usize>>,
+}
+impl SignatureSet {
+	/// Compiles a set of signatures
+	pub fn new<I: IntoIterator<Item = Signature>>(signatures: I) -> Self {
+		let signatures: Vec<Signature> = signatures.into_iter().collect();
+		let anchors: Vec<Option<Anchor>> = signatures.iter().map(Self::anchor).collect();
+		let unanchored = anchors
+			.iter()
+			.enumerate()
+			.filter(|(id, anchor)| anchor.is_none() && !signatures[*id].is_empty())
+			.map(|(id, _)| id)
+			.collect();
+
+		let mut set = SignatureSet {
+			signatures,
+			anchors,
+			unanchored,
+			transitions: vec![0; 256],
+			outputs: vec![Vec::new()],
+		};
+		set.build();
+		set
+	}
+
+	/// Picks the rarest window of at most [`MAX_ANCHOR_LEN`] fixed bytes, preferring longer windows
+	///
+	/// Anchors must be at a fixed offset from the start of a match, so they can't come after a gap or alternatives of different lengths.
+	fn anchor(signature: &Signature) -> Option<Anchor> {
+		let fixed: Vec<(usize, Option<u8>)> = matcher::fixed_bytes(signature).map(|(offset, byte)| (offset, byte.as_exact())).collect();
+		let mut best: Option<(Anchor, usize)> = None;
+		let mut i = 0;
+		while i < fixed.len() {
+			if fixed[i].1.is_none() {
+				i += 1;
+				continue;
+			}
+			// A run of exact bytes at consecutive offsets
+			let run_start = i;
+			i += 1;
+			while i < fixed.len() && fixed[i].1.is_some() && fixed[i].0 == fixed[i - 1].0 + 1 {
+				i += 1;
+			}
+			let run: Vec<u8> = fixed[run_start..i].iter().filter_map(|(_, byte)| *byte).collect();
+			let len = run.len().min(MAX_ANCHOR_LEN);
+			for start in 0..=run.len() - len {
+				let bytes = &run[start..start + len];
+				let commonness: usize = bytes.iter().copied().map(matcher::commonness).sum();
+				let better = match &best {
+					None => true,
+					Some((best, best_commonness)) => len > best.bytes.len() || (len == best.bytes.len() && commonness < *best_commonness),
+				};
+				if better {
+					let anchor = Anchor {
+						offset: fixed[run_start].0 + start,
+						bytes: bytes.to_vec(),
+					};
+					best = Some((anchor, commonness));
+				}
+			}
+		}
+		best.map(|(anchor, _)| anchor)
+	}
+
+	fn build(&mut self) {
+		// Build the trie, using 0 as "no transition" since nothing can transition back into the root
+		for (id, anchor) in self.anchors.iter().enumerate() {
+			let anchor = match anchor {
+				Some(anchor) => anchor,
+				None => continue,
+			};
+			let mut state = 0;
+			for byte in &anchor.bytes {
+				let byte = *byte as usize;
+				let next = self.transitions[state * 256 + byte] as usize;
+				state = if next != 0 {
+					next
+				} else {
+					let next = self.outputs.len();
+					self.transitions[state * 256 + byte] = next as u32;
+					self.transitions.extend_from_slice(&[0; 256]);
+					self.outputs.push(Vec::new());
+					next
+				};
+			}
+			self.outputs[state].push(id);
+		}
+
+		// Breadth-first, resolve failure links and turn the trie into a DFA
+		let mut fail = vec![0usize; self.outputs.len()];
+		let mut queue = std::collections::VecDeque::new();
+		for byte in 0..256 {
+			let next = self.transitions[byte] as usize;
+			if next != 0 {
+				queue.push_back(next);
+			}
+		}
+		while let Some(state) = queue.pop_front() {
+			let inherited = self.outputs[fail[state]].clone();
+			self.outputs[state].extend(inherited);
+			for byte in 0..256 {
+				let next = self.transitions[state * 256 + byte] as usize;
+				let fail_next = self.transitions[fail[state] * 256 + byte];
+				if next != 0 {
+					fail[next] = fail_next as usize;
+					queue.push_back(next);
+				} else {
+					self.transitions[state * 256 + byte] = fail_next;
+				}
+			}
+		}
+	}
+
+	/// Returns the number of signatures in the set
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.signatures.len()
+	}
+
+	/// Returns whether the set contains no signatures
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.signatures.is_empty()
+	}
+
+	/// Returns the signature with the given id
+	#[inline]
+	pub fn get(&self, id: usize) -> Option<&Signature> {
+		self.signatures.get(id)
+	}
+
+	/// Calls `f` with the id and offset of every match that starts before `before`, in no particular order
+	fn for_each_match<F: FnMut(usize, usize)>(&self, bytes: &[u8], before: usize, mut f: F) {
+		// Anchors of matches that start before `before` end at most this far past it
+		let anchor_end = self.anchors.iter().flatten().map(|anchor| anchor.offset + anchor.bytes.len()).max().unwrap_or(0);
+		let scanned = before.saturating_add(anchor_end.saturating_sub(1)).min(bytes.len());
+
+		let mut trace = matcher::Trace::default();
+		let mut state = 0;
+		for (i, byte) in bytes[..scanned].iter().enumerate() {
+			state = self.transitions[state * 256 + *byte as usize] as usize;
+			for &id in &self.outputs[state] {
+				let anchor = self.anchors[id].as_ref().unwrap();
+				let start = match (i + 1).checked_sub(anchor.offset + anchor.bytes.len()) {
+					Some(start) if start < before => start,
+					_ => continue,
+				};
+				trace.clear();
+				if matcher::match_at(&self.signatures[id], bytes, start, &mut trace).is_some() {
+					f(id, start);
+				}
+			}
+		}
+
+		for &id in &self.unanchored {
+			let mut from = 0;
+			while let Some(start) = matcher::find(&self.signatures[id], bytes, from, before) {
+				f(id, start);
+				from = start + 1;
+			}
+		}
+	}
+
+	/// Scans a slice of bytes for every signature in one pass
+	///
+	/// Returns the offset of the first occurrence of each signature, indexed by signature id
+	pub fn scan(&self, bytes: &[u8]) -> Vec<Option<usize>> {
+		let mut results = vec![None; self.len()];
+		self.for_each_match(bytes, usize::MAX, |id, offset| {
+			let result = &mut results[id];
+			if result.map(|result| offset < result).unwrap_or(true) {
+				*result = Some(offset);
+			}
+		});
+		results
+	}
+
+	/// Scans a slice of bytes for every occurrence of every signature in one pass
+	///
+	/// Matches are sorted by offset, then by signature id
+	pub fn scan_all(&self, bytes: &[u8]) -> Vec<SetMatch> {
+		self.scan_all_before(bytes, usize::MAX)
+	}
+
+	/// Scans a slice of bytes for every occurrence of every signature that starts before `before`
+	pub(crate) fn scan_all_before(&self, bytes: &[u8], before: usize) -> Vec<SetMatch> {
+		let mut matches = Vec::new();
+		self.for_each_match(bytes, before, |id, offset| matches.push(SetMatch { offset, id }));
+		matches.sort_unstable();
+		matches
+	}
+
+	/// Scans a loaded module for every signature in one pass
+	///
+	/// Each signature has its own result, indexed by signature id, with the same semantics as [`Signature::scan_module`]
+	pub unsafe fn scan_module<S: Into<ModuleSelector>>(&self, module: S) -> Result<Vec<Result<*mut u8, ModuleSigScanError>>, ModuleSigScanError> {
+		Ok(self.scan_loaded_module(&Module::resolve(module)?))
+	}
+
+	/// Scans an already resolved module for every signature in one pass
+	///
+	/// Each signature has its own result, indexed by signature id, with the same semantics as [`Module::scan_unique`]
+	pub unsafe fn scan_loaded_module(&self, module: &Module) -> Vec<Result<*mut u8, ModuleSigScanError>> {
+		let mut found: Vec<Vec<usize>> = vec![Vec::new(); self.len()];
+		for segment in module.readable_segments(None) {
+			let memory = segment.as_slice();
+			let mut matches = Vec::new();
+			self.for_each_match(memory, usize::MAX, |id, offset| matches.push((id, segment.address + offset)));
+			// Anchors are found out of order, addresses must be in ascending order within each segment
+			matches.sort_unstable();
+			for (id, address) in matches {
+				found[id].push(address);
+			}
+		}
+
+		found
+			.into_iter()
+			.map(|addresses| match addresses.len() {
+				0 => Err(module.not_found()),
+				1 => Ok(addresses[0] as *mut u8),
+				_ => Err(module.multiple_found(addresses)),
+			})
+			.collect()
+	}
+}
+impl std::iter::FromIterator<Signature> for SignatureSet {
+	fn from_iter<I: IntoIterator<Item = Signature>>(signatures: I) -> Self {
+		Self::new(signatures)
+	}
+}
+impl std::fmt::Debug for SignatureSet {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_list().entries(&self.signatures).finish()
+	}
+}
@@ -1,9 +1,349 @@
-use crate::{ModuleSigScanError, SigscanPtr, modulescan::Scanner};
+use crate::matcher::{self, Trace};
+use crate::address::Address;
+use crate::{Module, ModuleMatch, ModuleSelector, ModuleSigScanError, ResolveError, SegmentFlags, SigscanPtr};
 
-type SigByte = Option<u8>;
+use std::borrow::Cow;
 
-#[derive(Default, Clone, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Signature(Vec<SigByte>);
+/// A byte of a signature, which matches any byte `b` for which `b & mask == value`
+///
+/// A mask of `FF` matches exactly one byte, a mask of `00` matches any byte, and anything in between matches on some bits only,
+/// such as `4?` (value `40`, mask `F0`) which matches any byte from `40` to `4F`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SigByte {
+	value: u8,
+	mask: u8,
+}
+impl SigByte {
+	/// Matches any byte (`??`)
+	pub const ANY: SigByte = SigByte { value: 0, mask: 0 };
+
+	/// Matches exactly this byte
+	#[inline]
+	pub const fn exact(byte: u8) -> Self {
+		SigByte { value: byte, mask: 0xFF }
+	}
+
+	/// Matches any byte `b` for which `b & mask == value & mask`
+	#[inline]
+	pub const fn masked(value: u8, mask: u8) -> Self {
+		SigByte { value: value & mask, mask }
+	}
+
+	/// The bits this byte must have, where they are set in the mask
+	#[inline]
+	pub const fn value(self) -> u8 {
+		self.value
+	}
+
+	/// The bits of the byte that are checked
+	#[inline]
+	pub const fn mask(self) -> u8 {
+		self.mask
+	}
+
+	/// Returns whether this matches any byte
+	#[inline]
+	pub const fn is_any(self) -> bool {
+		self.mask == 0
+	}
+
+	/// Returns the byte this matches if it only matches exactly one byte
+	#[inline]
+	pub const fn as_exact(self) -> Option<u8> {
+		if self.mask == 0xFF {
+			Some(self.value)
+		} else {
+			None
+		}
+	}
+
+	/// Returns whether this matches the given byte
+	#[inline(always)]
+	pub const fn matches(self, byte: u8) -> bool {
+		byte & self.mask == self.value
+	}
+}
+impl From<u8> for SigByte {
+	#[inline]
+	fn from(byte: u8) -> Self {
+		SigByte::exact(byte)
+	}
+}
+impl From<Option<u8>> for SigByte {
+	#[inline]
+	fn from(byte: Option<u8>) -> Self {
+		byte.map(SigByte::exact).unwrap_or(SigByte::ANY)
+	}
+}
+impl std::fmt::Display for SigByte {
+	/// Formats this byte as `FF`, `??`, `4?`, `?8` or `value&mask` for any other mask
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self.mask {
+			0xFF => write!(f, "{:02X}", self.value),
+			0x00 => f.write_str("??"),
+			0xF0 => write!(f, "{:X}?", self.value >> 4),
+			0x0F => write!(f, "?{:X}", self.value),
+			_ => write!(f, "{:02X}&{:02X}", self.value, self.mask),
+		}
+	}
+}
+impl std::fmt::Debug for SigByte {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		std::fmt::Display::fmt(self, f)
+	}
+}
+impl std::str::FromStr for SigByte {
+	type Err = ();
+
+	/// Parses `FF`, `?`, `??`, `4?`, `?8` or `value&mask` (e.g. `40&F8`)
+	fn from_str(token: &str) -> Result<Self, ()> {
+		fn nibble(c: u8) -> Option<u8> {
+			(c as char).to_digit(16).map(|nibble| nibble as u8)
+		}
+		fn hex(token: &str) -> Option<u8> {
+			match token.as_bytes() {
+				[hi, lo] => Some(nibble(*hi)? << 4 | nibble(*lo)?),
+				_ => None,
+			}
+		}
+
+		match token.as_bytes() {
+			b"?" | b"??" => Ok(SigByte::ANY),
+			[b'?', lo] => nibble(*lo).map(|lo| SigByte::masked(lo, 0x0F)).ok_or(()),
+			[hi, b'?'] => nibble(*hi).map(|hi| SigByte::masked(hi << 4, 0xF0)).ok_or(()),
+			_ => match token.split_once('&') {
+				Some((value, mask)) => {
+					let (value, mask) = (hex(value).ok_or(())?, hex(mask).ok_or(())?);
+					if value & !mask != 0 {
+						// Bits that aren't checked can't be set
+						return Err(());
+					}
+					Ok(SigByte::masked(value, mask))
+				}
+				None => hex(token).map(SigByte::exact).ok_or(()),
+			},
+		}
+	}
+}
+
+/// An element of a signature
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SigElement {
+	/// A single byte
+	Byte(SigByte),
+
+	/// A run of at least `min` and at most `max` bytes of any value, written as `[min-max]` or `*{min,max}`
+	///
+	/// Gaps match as few bytes as possible, and only match more if the rest of the signature doesn't match otherwise.
+	Gap { min: usize, max: usize },
+
+	/// Any one of several signatures, written as `(74|75)` or `(8B 45 08 | 8B 4D 08)`
+	///
+	/// Alternatives are tried in order, and a later one is only used if the rest of the signature doesn't match otherwise.
+	Alt(Vec<Signature>),
+
+	/// `len` bytes of any value that are reported by name in a [`SigMatch`], written as `${'name:len}`
+	///
+	/// `${'name}` captures a position in the match without any bytes. Names are made up of ASCII letters, digits and underscores.
+	Capture { name: String, len: usize },
+}
+impl SigElement {
+	/// Returns the byte if this element is a single byte
+	#[inline]
+	pub fn as_byte(&self) -> Option<SigByte> {
+		match self {
+			SigElement::Byte(byte) => Some(*byte),
+			_ => None,
+		}
+	}
+
+	/// Returns whether this element matches any byte(s)
+	#[inline]
+	pub fn is_any(&self) -> bool {
+		match self {
+			SigElement::Byte(byte) => byte.is_any(),
+			SigElement::Gap { .. } | SigElement::Capture { .. } => true,
+			SigElement::Alt(alternatives) => alternatives.iter().all(|alternative| alternative.iter().all(SigElement::is_any)),
+		}
+	}
+
+	/// The fewest bytes this element can match
+	#[inline]
+	pub fn min_len(&self) -> usize {
+		match self {
+			SigElement::Byte(_) => 1,
+			SigElement::Gap { min, .. } => *min,
+			SigElement::Capture { len, .. } => *len,
+			SigElement::Alt(alternatives) => alternatives.iter().map(Signature::min_len).min().unwrap_or(0),
+		}
+	}
+
+	/// The most bytes this element can match
+	#[inline]
+	pub fn max_len(&self) -> usize {
+		match self {
+			SigElement::Byte(_) => 1,
+			SigElement::Gap { max, .. } => *max,
+			SigElement::Capture { len, .. } => *len,
+			SigElement::Alt(alternatives) => alternatives.iter().map(Signature::max_len).max().unwrap_or(0),
+		}
+	}
+}
+impl From<SigByte> for SigElement {
+	#[inline]
+	fn from(byte: SigByte) -> Self {
+		SigElement::Byte(byte)
+	}
+}
+impl From<u8> for SigElement {
+	#[inline]
+	fn from(byte: u8) -> Self {
+		SigElement::Byte(SigByte::exact(byte))
+	}
+}
+impl From<Option<u8>> for SigElement {
+	#[inline]
+	fn from(byte: Option<u8>) -> Self {
+		SigElement::Byte(SigByte::from(byte))
+	}
+}
+impl std::fmt::Display for SigElement {
+	/// Formats this element as a byte, a gap as `[min-max]` (or `[len]` if it has a fixed length), alternatives as `(74|75)` or a capture as `${'name:len}`
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SigElement::Byte(byte) => write!(f, "{}", byte),
+			SigElement::Capture { name, len: 0 } => write!(f, "${{'{}}}", name),
+			SigElement::Capture { name, len } => write!(f, "${{'{}:{}}}", name, len),
+			SigElement::Gap { min, max } if min == max => write!(f, "[{}]", min),
+			SigElement::Gap { min, max } => write!(f, "[{}-{}]", min, max),
+			SigElement::Alt(alternatives) => {
+				let separator = if alternatives.iter().all(|alternative| alternative.len() == 1) { "|" } else { " | " };
+				f.write_str("(")?;
+				for (i, alternative) in alternatives.iter().enumerate() {
+					if i != 0 {
+						f.write_str(separator)?;
+					}
+					write!(f, "{:?}", alternative)?;
+				}
+				f.write_str(")")
+			}
+		}
+	}
+}
+impl std::fmt::Debug for SigElement {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		std::fmt::Display::fmt(self, f)
+	}
+}
+impl std::str::FromStr for SigElement {
+	type Err = ();
+
+	/// Parses a byte (see [`SigByte`]), a gap written as `[min-max]`, `[len]`, `*{min,max}` or `*{len}`, or a capture written as `${'name:len}` or `${'name}`
+	fn from_str(token: &str) -> Result<Self, ()> {
+		// Only plain decimal numbers, `usize::from_str` would also accept a sign
+		let parse = |len: &str| {
+			if !len.is_empty() && len.bytes().all(|c| c.is_ascii_digit()) {
+				len.parse::<usize>().map_err(|_| ())
+			} else {
+				Err(())
+			}
+		};
+
+		if let Some(capture) = token.strip_prefix("${'").and_then(|capture| capture.strip_suffix('}')) {
+			let (name, len) = match capture.split_once(':') {
+				Some((name, len)) => (name, parse(len)?),
+				None => (capture, 0),
+			};
+			if name.is_empty() || !name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_') {
+				return Err(());
+			}
+			return Ok(SigElement::Capture { name: name.to_string(), len });
+		}
+
+		let gap = token
+			.strip_prefix('[')
+			.and_then(|gap| gap.strip_suffix(']'))
+			.map(|gap| (gap, '-'))
+			.or_else(|| token.strip_prefix("*{").and_then(|gap| gap.strip_suffix('}')).map(|gap| (gap, ',')));
+		let (min, max) = match gap {
+			Some((gap, separator)) => gap.split_once(separator).unwrap_or((gap, gap)),
+			None => return token.parse().map(SigElement::Byte),
+		};
+		let (min, max) = (parse(min)?, parse(max)?);
+		if min > max {
+			return Err(());
+		}
+		Ok(SigElement::Gap { min, max })
+	}
+}
+
+/// A match of a [`Signature`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SigMatch<'a> {
+	/// The offset of the first byte of the match in the scanned bytes
+	pub offset: usize,
+
+	/// The bytes that matched, which only ever differ in length from the signature if it contains gaps or alternatives
+	pub bytes: &'a [u8],
+
+	/// The number of bytes each gap of the signature matched, in the order they were matched
+	pub gaps: Vec<usize>,
+
+	/// The captures of the signature, in the order they were matched
+	///
+	/// Captures in alternatives that didn't match are left out.
+	pub captures: Vec<Capture<'a>>,
+}
+impl<'a> SigMatch<'a> {
+	/// The number of bytes matched
+	#[inline]
+	pub fn len(&self) -> usize {
+		self.bytes.len()
+	}
+
+	/// Returns whether the match is empty, which it can only be if the signature is made up of empty gaps and captures
+	#[inline]
+	pub fn is_empty(&self) -> bool {
+		self.bytes.is_empty()
+	}
+
+	/// The address of the first byte of the match
+	#[inline]
+	pub fn address(&self) -> usize {
+		self.bytes.as_ptr() as usize
+	}
+
+	/// Returns the first capture with the given name
+	#[inline]
+	pub fn capture(&self, name: &str) -> Option<&Capture<'a>> {
+		self.captures.iter().find(|capture| capture.name == name)
+	}
+}
+
+/// A capture of a [`SigMatch`], written as `${'name:len}` in a signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Capture<'a> {
+	/// The name of the capture
+	pub name: &'a str,
+
+	/// The offset of the first byte of the capture in the scanned bytes
+	pub offset: usize,
+
+	/// The captured bytes
+	pub bytes: &'a [u8],
+}
+impl Capture<'_> {
+	/// The address of the first byte of the capture
+	#[inline]
+	pub fn address(&self) -> usize {
+		self.bytes.as_ptr() as usize
+	}
+}
+
+#[derive(Default, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Signature(Vec<SigElement>);
 impl Signature {
 	/// Creates a signature with a specified capacity of bytes
 	#[inline]
@@ -14,91 +354,294 @@ impl Signature {
 	#[inline]
 	/// Pushes a byte into this signature
 	pub fn push_byte(&mut self, byte: u8) {
-		self.push(Some(byte));
+		self.push(SigElement::Byte(SigByte::exact(byte)));
 	}
 
 	#[inline]
 	/// Pushes a `??` into this signature
 	pub fn push_any(&mut self) {
-		self.push(None);
+		self.push(SigElement::Byte(SigByte::ANY));
+	}
+
+	#[inline]
+	/// Pushes a byte into this signature that only has to match on the bits set in `mask`
+	pub fn push_masked(&mut self, value: u8, mask: u8) {
+		self.push(SigElement::Byte(SigByte::masked(value, mask)));
+	}
+
+	#[inline]
+	/// Pushes a gap of at least `min` and at most `max` bytes of any value into this signature
+	pub fn push_gap(&mut self, min: usize, max: usize) {
+		self.push(SigElement::Gap { min, max });
+	}
+
+	/// The fewest bytes a match of this signature can span
+	#[inline]
+	pub fn min_len(&self) -> usize {
+		self.iter().map(SigElement::min_len).fold(0, usize::saturating_add)
+	}
+
+	/// The most bytes a match of this signature can span
+	#[inline]
+	pub fn max_len(&self) -> usize {
+		self.iter().map(SigElement::max_len).fold(0, usize::saturating_add)
 	}
 
 	/// Scans a slice of bytes for the signature
+	#[inline]
 	pub fn scan(&self, bytes: &[u8]) -> Option<usize> {
-		let mut iter_bytes = bytes.iter().enumerate();
-		let mut start = 0;
-		let mut i = 0;
-		while i < self.len() {
-			let (byte_pos, byte) = iter_bytes.next()?;
-			if let Some(sig_byte) = &self.0[i] {
-				if sig_byte == byte {
-					i += 1;
-					if start == 0 {
-						start = byte_pos;
-					}
-				} else {
-					start = 0;
-					i = 0;
-				}
-			} else {
-				i += 1;
-			}
+		matcher::find(self, bytes, 0, usize::MAX)
+	}
+
+	/// Scans a slice of bytes for the signature, returning where it matched, its captures and the length of each of its gaps
+	#[inline]
+	pub fn scan_match<'a>(&'a self, bytes: &'a [u8]) -> Option<SigMatch<'a>> {
+		self.scan_all(bytes).matches().next()
+	}
+
+	/// Scans a slice of bytes for every occurrence of the signature
+	///
+	/// Matches are yielded lazily in ascending order and may overlap each other. Use [`ScanAll::non_overlapping`] to skip over matches that overlap a previous one.
+	#[inline]
+	pub fn scan_all<'a>(&'a self, bytes: &'a [u8]) -> ScanAll<'a> {
+		ScanAll {
+			signature: self,
+			bytes,
+			pos: 0,
+			before: usize::MAX,
+			overlapping: true,
 		}
-		Some(start)
+	}
+
+	/// Scans a slice of bytes for exactly one occurrence of the signature
+	///
+	/// Every match counts, including matches that overlap each other, the same as [`Module::scan_unique`]
+	#[inline]
+	pub fn scan_unique(&self, bytes: &[u8]) -> Result<usize, UniqueScanError> {
+		self.scan_all(bytes).unique()
 	}
 
 	/// Increments the pointer until the signature is found
 	///
 	/// The returned pointer will be the first byte of the signature
-	pub unsafe fn scan_ptr<P: SigscanPtr>(&self, mut ptr: P, max: P) -> Option<P> {
-		let mut i = 0;
-		while ptr < max {
-			let byte = ptr.byte();
-			let sig_byte = &self.0[i];
-			if let Some(sig_byte) = sig_byte {
-				if *sig_byte != byte {
-					i = 0;
-					ptr = ptr.next();
-					continue;
-				}
-			}
-			i += 1;
-			if i >= self.len() {
-				return Some(ptr.rewind(self.len() - 1));
-			}
-			ptr = ptr.next();
+	pub unsafe fn scan_ptr<P: SigscanPtr>(&self, ptr: P, max: P) -> Option<P> {
+		if ptr > max {
+			return None;
 		}
-		None
+		let bytes = ptr_bytes(ptr, max);
+		self.scan(&bytes).map(|offset| ptr.forward(offset))
+	}
+
+	/// Scans the memory between `ptr` and `max` for exactly one occurrence of the signature
+	///
+	/// See [`Signature::scan_unique`], the offsets of [`UniqueScanError::MultipleFound`] are relative to `ptr`
+	pub unsafe fn scan_ptr_unique<P: SigscanPtr>(&self, ptr: P, max: P) -> Result<P, UniqueScanError> {
+		if ptr > max {
+			return Err(UniqueScanError::NotFound);
+		}
+		let bytes = ptr_bytes(ptr, max);
+		self.scan_unique(&bytes).map(|offset| ptr.forward(offset))
+	}
+
+	/// Scans a slice of bytes for the signature, returning an [`Address`] that can be followed within the slice
+	#[inline]
+	pub fn scan_address<'a>(&self, bytes: &'a [u8]) -> Option<Address<'a>> {
+		self.scan(bytes).and_then(|offset| Address::in_slice(bytes, offset).ok())
+	}
+
+	/// Increments the pointer until the signature is found, returning an [`Address`] that can be followed between `ptr` and `max`
+	///
+	/// The memory between `ptr` and `max` must stay valid for as long as the address is used
+	pub unsafe fn scan_ptr_address<'a>(&self, ptr: *const u8, max: *const u8) -> Option<Address<'a>> {
+		if ptr > max {
+			return None;
+		}
+		self.scan_address(std::slice::from_raw_parts(ptr, max as usize - ptr as usize))
 	}
 
 	/// Scan a loaded module for a signature
-	pub unsafe fn scan_module<S: AsRef<str>>(&self, module: S) -> Result<*mut u8, ModuleSigScanError> {
-		let scanner = Scanner::for_module(module.as_ref()).ok_or(ModuleSigScanError::InvalidModule)?;
-		scanner.find(&*self)
+	///
+	/// The module can be given as its exact path or file name, or as any [`ModuleSelector`]. Every readable segment of the module is scanned
+	/// for exactly one occurrence of the signature, like [`Signature::scan_unique`]
+	///
+	/// This looks up the module every time, use [`Module`] to scan the same module repeatedly
+	pub unsafe fn scan_module<S: Into<ModuleSelector>>(&self, module: S) -> Result<*mut u8, ModuleSigScanError> {
+		self.scan_module_segments(module, None).map(|found| found.ptr)
+	}
+
+	/// Scan a loaded module for a signature, returning an [`Address`] that can be followed within the module's readable segments
+	///
+	/// The module must stay loaded for as long as the address is used
+	pub unsafe fn scan_module_address<S: Into<ModuleSelector>>(&self, module: S) -> Result<Address<'static>, ResolveError> {
+		Module::resolve(module)?.scan_unique_address(self)
+	}
+
+	/// Scan a loaded module for a signature, returning the match with its captures
+	///
+	/// Offsets in the match are relative to the segment it was found in, [`SigMatch::address`] and [`Capture::address`] give addresses.
+	pub unsafe fn scan_module_match<S: Into<ModuleSelector>>(&self, module: S) -> Result<SigMatch<'_>, ModuleSigScanError> {
+		Module::resolve(module)?.scan_unique_match(self)
+	}
+
+	/// Scan the segments of a loaded module for a signature, reporting which segment it was found in
+	///
+	/// If `flags` is given, only segments with exactly those flags are scanned (e.g. `SegmentFlags::RX` for code)
+	pub unsafe fn scan_module_segments<S: Into<ModuleSelector>>(&self, module: S, flags: Option<SegmentFlags>) -> Result<ModuleMatch, ModuleSigScanError> {
+		Module::resolve(module)?.scan_unique_segments(self, flags)
+	}
+}
+/// Iterator over every occurrence of a [`Signature`] in a slice of bytes
+///
+/// Created by [`Signature::scan_all`]
+#[derive(Clone)]
+pub struct ScanAll<'a> {
+	signature: &'a Signature,
+	bytes: &'a [u8],
+	pos: usize,
+	/// Matches must start before this offset
+	before: usize,
+	overlapping: bool,
+}
+impl<'a> ScanAll<'a> {
+	/// Only yield matches that don't overlap a previously yielded match
+	#[inline]
+	pub fn non_overlapping(mut self) -> Self {
+		self.overlapping = false;
+		self
+	}
+
+	/// Only yield matches that start before `before`, without searching past where they could start
+	#[inline]
+	pub(crate) fn starting_before(mut self, before: usize) -> Self {
+		self.before = before;
+		self
+	}
+
+	/// Yield a [`SigMatch`] for each match instead of its offset
+	#[inline]
+	pub fn matches(self) -> ScanMatches<'a> {
+		ScanMatches(self)
+	}
+
+	/// Returns the only match, or an error listing every match if there is more than one
+	#[inline]
+	pub fn unique(self) -> Result<usize, UniqueScanError> {
+		unique(self).map_err(|offsets| if offsets.is_empty() { UniqueScanError::NotFound } else { UniqueScanError::MultipleFound { offsets } })
+	}
+
+	/// Finds the next match, returning its start and end
+	fn next_match(&mut self, trace: &mut Trace<'a>) -> Option<(usize, usize)> {
+		let (start, end) = matcher::find_match(self.signature, self.bytes, self.pos, self.before, trace)?;
+		// A signature made up of empty gaps matches zero bytes, which mustn't be matched forever
+		self.pos = if self.overlapping { start + 1 } else { end.max(start + 1) };
+		Some((start, end))
+	}
+}
+impl Iterator for ScanAll<'_> {
+	type Item = usize;
+
+	#[inline]
+	fn next(&mut self) -> Option<usize> {
+		self.next_match(&mut Trace::default()).map(|(start, _)| start)
+	}
+}
+
+/// The memory between `ptr` and `max`, which is copied a byte at a time if the pointers can't be read directly
+unsafe fn ptr_bytes<'a, P: SigscanPtr>(ptr: P, max: P) -> Cow<'a, [u8]> {
+	match (ptr.as_ptr(), max.as_ptr()) {
+		(Some(start), Some(end)) => Cow::Borrowed(std::slice::from_raw_parts(start, end as usize - start as usize)),
+		_ => {
+			let mut bytes = Vec::new();
+			let mut ptr = ptr;
+			while ptr < max {
+				bytes.push(ptr.byte());
+				ptr = ptr.next();
+			}
+			Cow::Owned(bytes)
+		}
+	}
+}
+
+/// Returns the only item, or every item if there isn't exactly one
+pub(crate) fn unique<T>(mut items: impl Iterator<Item = T>) -> Result<T, Vec<T>> {
+	let first = items.next().ok_or_else(Vec::new)?;
+	match items.next() {
+		None => Ok(first),
+		Some(second) => Err(std::iter::once(first).chain(std::iter::once(second)).chain(items).collect()),
+	}
+}
+
+/// Iterator over every [`SigMatch`] of a [`Signature`] in a slice of bytes
+///
+/// Created by [`ScanAll::matches`]
+#[derive(Clone)]
+pub struct ScanMatches<'a>(ScanAll<'a>);
+impl<'a> Iterator for ScanMatches<'a> {
+	type Item = SigMatch<'a>;
+
+	fn next(&mut self) -> Option<SigMatch<'a>> {
+		let mut trace = Trace::default();
+		let (offset, end) = self.0.next_match(&mut trace)?;
+		let bytes = self.0.bytes;
+		Some(SigMatch {
+			offset,
+			bytes: &bytes[offset..end],
+			gaps: trace.gaps,
+			captures: trace
+				.captures
+				.into_iter()
+				.map(|(name, offset, len)| Capture {
+					name,
+					offset,
+					bytes: &bytes[offset..offset + len],
+				})
+				.collect(),
+		})
+	}
+}
+
+impl From<Vec<SigElement>> for Signature {
+	fn from(elements: Vec<SigElement>) -> Self {
+		Self(elements)
+	}
+}
+impl From<&[SigElement]> for Signature {
+	fn from(elements: &[SigElement]) -> Self {
+		Self(elements.to_vec())
+	}
+}
+impl From<Vec<SigByte>> for Signature {
+	fn from(bytes: Vec<SigByte>) -> Self {
+		Self(bytes.into_iter().map(SigElement::Byte).collect())
+	}
+}
+impl From<&[SigByte]> for Signature {
+	fn from(bytes: &[SigByte]) -> Self {
+		Self(bytes.iter().copied().map(SigElement::Byte).collect())
 	}
 }
 impl From<Vec<Option<u8>>> for Signature {
 	fn from(bytes: Vec<Option<u8>>) -> Self {
-		Self(bytes)
+		Self(bytes.into_iter().map(SigElement::from).collect())
 	}
 }
 impl From<&[Option<u8>]> for Signature {
 	fn from(bytes: &[Option<u8>]) -> Self {
-		Self(bytes.to_vec())
+		Self(bytes.iter().copied().map(SigElement::from).collect())
 	}
 }
 impl From<Vec<u8>> for Signature {
 	fn from(bytes: Vec<u8>) -> Self {
-		Self(bytes.into_iter().map(Some).collect())
+		Self(bytes.into_iter().map(SigElement::from).collect())
 	}
 }
 impl From<&[u8]> for Signature {
 	fn from(bytes: &[u8]) -> Self {
-		Self(bytes.iter().map(|byte| Some(*byte)).collect())
+		Self(bytes.iter().copied().map(SigElement::from).collect())
 	}
 }
 impl std::ops::Deref for Signature {
-	type Target = Vec<SigByte>;
+	type Target = Vec<SigElement>;
 
 	#[inline(always)]
 	fn deref(&self) -> &Self::Target {
@@ -123,50 +666,256 @@ impl std::fmt::Debug for Signature {
 	}
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// An error scanning for exactly one occurrence of a signature
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UniqueScanError {
+	/// Failed to find the signature
+	NotFound,
+
+	/// Found multiple occurrences of the signature, at these offsets
+	MultipleFound { offsets: Vec<usize> },
+}
+impl std::fmt::Display for UniqueScanError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			UniqueScanError::NotFound => f.write_str("signature not found"),
+			UniqueScanError::MultipleFound { offsets } => {
+				write!(f, "signature found {} times, at offsets", offsets.len())?;
+				for (i, offset) in offsets.iter().enumerate() {
+					write!(f, "{} {:#x}", if i == 0 { "" } else { "," }, offset)?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+impl std::error::Error for UniqueScanError {}
+
+/// An error parsing a signature
+///
+/// Code written against the old fieldless variants can compare [`SignatureParseError::kind`] instead, e.g. `err.kind() == SignatureParseErrorKind::InvalidByte`,
+/// or compare the error itself with a [`SignatureParseErrorKind`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum SignatureParseError {
 	/// A byte in this signature was invalid.
 	///
-	/// Each byte must be `??` or a 2-digit hex (e.g. `FF`) and optionally separated by spaces (e.g. `FF 00 ?? FF`)
+	/// Each byte must be `??`, a 2-digit hex (e.g. `FF`), a half wildcard (e.g. `4?` or `?8`), a masked byte (e.g. `40&F8`)
+	/// a gap (e.g. `[2-6]` or `*{0,8}`) or a capture (e.g. `${'rel:4}`), and optionally separated by spaces (e.g. `FF 00 ?? 4? [1-4] FF`).
+	/// Groups of alternatives (e.g. `(74|75)`) are made up of the same.
+	InvalidByte {
+		/// The byte offset of the offending token in the string
+		position: usize,
+
+		/// The offending token, which is empty if two separators were next to each other
+		token: String,
+	},
+
+	/// The string was empty.
+	Empty,
+
+	/// The signature only contained `??` and gaps
+	OnlyAny,
+
+	/// The mask of a code style signature isn't the same length as its bytes
+	MaskLength { bytes: usize, mask: usize },
+
+	/// A group of alternatives was never closed, or a `)` has no group to close
+	UnbalancedGroup {
+		/// The byte offset of the offending parenthesis in the string
+		position: usize,
+	},
+
+	/// A capture has the same name as another capture that can be part of the same match
+	DuplicateCapture {
+		/// The byte offset of the second capture in the string
+		position: usize,
+
+		/// The name of the capture
+		name: String,
+	},
+}
+impl SignatureParseError {
+	/// Returns the kind of error, without its context
+	pub fn kind(&self) -> SignatureParseErrorKind {
+		match self {
+			SignatureParseError::InvalidByte { .. } => SignatureParseErrorKind::InvalidByte,
+			SignatureParseError::Empty => SignatureParseErrorKind::Empty,
+			SignatureParseError::OnlyAny => SignatureParseErrorKind::OnlyAny,
+			SignatureParseError::MaskLength { .. } => SignatureParseErrorKind::MaskLength,
+			SignatureParseError::UnbalancedGroup { .. } => SignatureParseErrorKind::UnbalancedGroup,
+			SignatureParseError::DuplicateCapture { .. } => SignatureParseErrorKind::DuplicateCapture,
+		}
+	}
+}
+impl PartialEq<SignatureParseErrorKind> for SignatureParseError {
+	#[inline]
+	fn eq(&self, kind: &SignatureParseErrorKind) -> bool {
+		self.kind() == *kind
+	}
+}
+impl std::fmt::Display for SignatureParseError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			SignatureParseError::InvalidByte { position, token } if token.is_empty() => write!(f, "unexpected separator at position {} in signature", position),
+			SignatureParseError::InvalidByte { position, token } => write!(f, "invalid byte `{}` at position {} in signature", token, position),
+			SignatureParseError::Empty => f.write_str("empty signature"),
+			SignatureParseError::OnlyAny => f.write_str("signature only contains `??` bytes"),
+			SignatureParseError::MaskLength { bytes, mask } => write!(f, "signature has {} bytes but its mask has {}", bytes, mask),
+			SignatureParseError::UnbalancedGroup { position } => write!(f, "unbalanced parenthesis at position {} in signature", position),
+			SignatureParseError::DuplicateCapture { position, name } => write!(f, "duplicate capture `{}` at position {} in signature", name, position),
+		}
+	}
+}
+impl std::error::Error for SignatureParseError {}
+
+/// The kind of a [`SignatureParseError`], without its context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SignatureParseErrorKind {
+	/// A byte in this signature was invalid.
 	InvalidByte,
 
 	/// The string was empty.
 	Empty,
 
-	/// The signature only contained `??`
+	/// The signature only contained `??` and gaps
 	OnlyAny,
+
+	/// The mask of a code style signature isn't the same length as its bytes
+	MaskLength,
+
+	/// A group of alternatives was never closed, or a `)` has no group to close
+	UnbalancedGroup,
+
+	/// A capture has the same name as another capture that can be part of the same match
+	DuplicateCapture,
 }
-impl std::str::FromStr for Signature {
-	type Err = SignatureParseError;
 
-	fn from_str(s: &str) -> Result<Self, Self::Err> {
-		let mut added_byte = false;
+/// Parses the syntax of [`Signature::from_str`], which nests in groups of alternatives
+struct Parser<'a> {
+	s: &'a str,
+	pos: usize,
+	end: usize,
+
+	/// The names of the captures that can be part of the same match as the next element
+	captures: Vec<String>,
+}
+impl Parser<'_> {
+	#[inline]
+	fn peek(&self) -> Option<u8> {
+		if self.pos < self.end {
+			Some(self.s.as_bytes()[self.pos])
+		} else {
+			None
+		}
+	}
 
-		let mut signature = Signature::with_capacity(f32::ceil(s.len() as f32 / 2.) as usize);
+	/// Parses space separated elements until the end of the string, or the end of an alternative if `in_group`
+	fn sequence(&mut self, in_group: bool) -> Result<Vec<SigElement>, SignatureParseError> {
+		let mut elements = Vec::new();
+		loop {
+			elements.push(self.element(in_group)?);
+			match self.peek() {
+				None => return Ok(elements),
+				Some(b' ') => {
+					self.pos += 1;
+					// A space is allowed on either side of a `|` and before a `)`
+					if in_group && matches!(self.peek(), Some(b'|') | Some(b')')) {
+						return Ok(elements);
+					}
+				}
+				Some(b'|') | Some(b')') if in_group => return Ok(elements),
+				Some(b')') => return Err(SignatureParseError::UnbalancedGroup { position: self.pos }),
+				Some(_) => {
+					let token = &self.s[self.pos..self.pos + 1];
+					return Err(SignatureParseError::InvalidByte {
+						position: self.pos,
+						token: token.to_string(),
+					});
+				}
+			}
+		}
+	}
 
-		let trimmed = s.trim();
-		if trimmed.is_empty() {
-			return Err(SignatureParseError::Empty);
+	fn element(&mut self, in_group: bool) -> Result<SigElement, SignatureParseError> {
+		let start = self.pos;
+		match self.peek() {
+			Some(b'(') => {
+				self.pos += 1;
+				self.skip_space();
+				let mut alternatives = Vec::new();
+				// Alternatives can't be part of the same match as each other, but they can with whatever follows the group
+				let before = self.captures.len();
+				let mut captures = Vec::new();
+				loop {
+					alternatives.push(Signature(self.sequence(true)?));
+					captures.extend(self.captures.drain(before..));
+					match self.peek() {
+						Some(b'|') => {
+							self.pos += 1;
+							self.skip_space();
+						}
+						Some(b')') => {
+							self.pos += 1;
+							self.captures.extend(captures);
+							return Ok(SigElement::Alt(alternatives));
+						}
+						_ => return Err(SignatureParseError::UnbalancedGroup { position: start }),
+					}
+				}
+			}
+			Some(b')') if !in_group => return Err(SignatureParseError::UnbalancedGroup { position: start }),
+			_ => {}
 		}
 
-		for byte in trimmed.split(' ') {
-			match (byte.len(), byte) {
-				(1, "?") | (2, "??") => signature.push_any(),
-				(2, _) => {
-					added_byte = true;
-					signature.push_byte(u8::from_str_radix(byte, 16).map_err(|_| SignatureParseError::InvalidByte)?);
-				},
-				_ => return Err(SignatureParseError::InvalidByte)
+		let len = self.s[start..self.end].find([' ', '(', ')', '|']).unwrap_or(self.end - start);
+		let token = &self.s[start..start + len];
+		self.pos += len;
+		let element = token.parse().map_err(|_| SignatureParseError::InvalidByte {
+			position: start,
+			token: token.to_string(),
+		})?;
+		if let SigElement::Capture { name, .. } = &element {
+			if self.captures.contains(name) {
+				return Err(SignatureParseError::DuplicateCapture {
+					position: start,
+					name: name.clone(),
+				});
 			}
+			self.captures.push(name.clone());
+		}
+		Ok(element)
+	}
+
+	#[inline]
+	fn skip_space(&mut self) {
+		if self.peek() == Some(b' ') {
+			self.pos += 1;
+		}
+	}
+}
+
+impl std::str::FromStr for Signature {
+	type Err = SignatureParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s.trim().is_empty() {
+			return Err(SignatureParseError::Empty);
 		}
 
+		let mut parser = Parser {
+			s,
+			pos: s.len() - s.trim_start().len(),
+			end: s.trim_end().len(),
+			captures: Vec::new(),
+		};
+		let signature = Signature(parser.sequence(false)?);
+
 		if signature.is_empty() {
 			Err(SignatureParseError::Empty)
-		} else if !added_byte {
+		} else if signature.iter().all(SigElement::is_any) {
 			Err(SignatureParseError::OnlyAny)
 		} else {
-			signature.shrink_to_fit();
 			Ok(signature)
 		}
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,175 @@
+use crate::{Signature, SignatureSet};
+
+use std::io::{self, Read};
+
+/// The most bytes a match can extend past the end of a chunk, so that patterns with very long gaps don't keep the whole stream
+pub(crate) const MAX_OVERLAP: usize = 1 << 20;
+
+/// A match of a [`SignatureSet`] reported by a [`StreamScanner`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamMatch {
+	/// The offset of the first byte of the match in the stream
+	pub offset: u64,
+
+	/// The id of the signature that matched, which is its index in the set
+	pub id: usize,
+}
+
+/// Something a [`StreamScanner`] can search for, a [`Signature`] or a [`SignatureSet`]
+pub trait StreamPattern {
+	/// What is reported for each match, which includes its offset in the stream
+	type Match;
+
+	/// The most bytes a match can span, which is how much of the stream is kept to find matches that span chunks, up to 1 MiB
+	fn max_len(&self) -> usize;
+
+	/// Scans `bytes`, which are at `offset` in the stream, calling `found` for every match that starts before `end` in ascending order
+	fn scan_stream(&self, bytes: &[u8], offset: u64, end: usize, found: &mut dyn FnMut(Self::Match));
+}
+impl StreamPattern for Signature {
+	type Match = u64;
+
+	#[inline]
+	fn max_len(&self) -> usize {
+		Signature::max_len(self)
+	}
+
+	fn scan_stream(&self, bytes: &[u8], offset: u64, end: usize, found: &mut dyn FnMut(u64)) {
+		for pos in self.scan_all(bytes).starting_before(end) {
+			found(offset + pos as u64);
+		}
+	}
+}
+impl StreamPattern for SignatureSet {
+	type Match = StreamMatch;
+
+	#[inline]
+	fn max_len(&self) -> usize {
+		(0..self.len()).filter_map(|id| self.get(id)).map(Signature::max_len).max().unwrap_or(0)
+	}
+
+	fn scan_stream(&self, bytes: &[u8], offset: u64, end: usize, found: &mut dyn FnMut(StreamMatch)) {
+		for found_match in self.scan_all_before(bytes, end) {
+			found(StreamMatch {
+				offset: offset + found_match.offset as u64,
+				id: found_match.id,
+			});
+		}
+	}
+}
+
+/// Scans data that arrives in chunks, such as from a pipe or a socket, without keeping all of it
+///
+/// The end of the data fed so far is kept to find matches that span chunks, as many bytes as the longest match can span. Every
+/// match is reported once, at its offset from the start of the stream, in ascending order.
+///
+/// At most 1 MiB is kept, so a match of a pattern with longer gaps is only found if it extends less than 1 MiB past the end
+/// of the chunk it starts in.
+///
+/// ```ignore
+/// let signature = signature!("E8 ? ? ? ? 48 85 C0");
+/// let mut scanner = StreamScanner::new(&signature);
+/// let mut offsets: Vec<u64> = scanner.feed(&chunk);
+/// offsets.extend(scanner.finish());
+///
+/// let offsets: Vec<u64> = StreamScanner::new(&signature).scan_reader(std::io::stdin())?;
+/// ```
+pub struct StreamScanner<'p, P: StreamPattern + ?Sized = Signature> {
+	pattern: &'p P,
+	/// How many bytes a match can extend past its first byte, up to `MAX_OVERLAP`
+	overlap: usize,
+	buffer: Vec<u8>,
+	/// The offset of the first byte of the buffer in the stream
+	offset: u64,
+	/// The index in the buffer of the first start of a match that hasn't been scanned for, bytes before it are only kept until the buffer is compacted
+	start: usize,
+}
+impl<'p, P: StreamPattern + ?Sized> StreamScanner<'p, P> {
+	/// A scanner at the start of a stream
+	pub fn new(pattern: &'p P) -> Self {
+		StreamScanner {
+			pattern,
+			overlap: pattern.max_len().saturating_sub(1).min(MAX_OVERLAP),
+			buffer: Vec::new(),
+			offset: 0,
+			start: 0,
+		}
+	}
+
+	/// How many bytes have been fed
+	#[inline]
+	pub fn position(&self) -> u64 {
+		self.offset + self.buffer.len() as u64
+	}
+
+	/// Scans the next chunk of the stream, returning the matches that can't change with more data
+	///
+	/// Matches that start in the end of the chunk may need bytes that haven't arrived yet, they are returned by a later call.
+	pub fn feed(&mut self, chunk: &[u8]) -> Vec<P::Match> {
+		let mut found = Vec::new();
+		self.feed_with(chunk, |found_match| found.push(found_match));
+		found
+	}
+
+	/// Ends the stream, returning the matches in the data kept from the end of it
+	///
+	/// More data can be fed afterwards, at the following offsets, but matches won't span the end.
+	pub fn finish(&mut self) -> Vec<P::Match> {
+		let mut found = Vec::new();
+		self.finish_with(0, |found_match| found.push(found_match));
+		found
+	}
+
+	/// Scans everything read from a reader until it ends, returning every match in ascending order
+	///
+	/// The stream continues from any data that was already fed, and is finished at the end of the reader.
+	pub fn scan_reader<R: Read>(&mut self, reader: R) -> io::Result<Vec<P::Match>> {
+		self.scan_reader_chunks(reader, 64 << 10)
+	}
+
+	pub(crate) fn scan_reader_chunks<R: Read>(&mut self, mut reader: R, chunk_size: usize) -> io::Result<Vec<P::Match>> {
+		let mut chunk = vec![0; chunk_size];
+		let mut found = Vec::new();
+		loop {
+			match reader.read(&mut chunk) {
+				Ok(0) => break,
+				Ok(read) => self.feed_with(&chunk[..read], |found_match| found.push(found_match)),
+				Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+				Err(error) => return Err(error),
+			}
+		}
+		self.finish_with(0, |found_match| found.push(found_match));
+		Ok(found)
+	}
+
+	pub(crate) fn feed_with(&mut self, chunk: &[u8], mut found: impl FnMut(P::Match)) {
+		// Only move the kept bytes to the front once there are more scanned bytes before them, so that feeding many small chunks doesn't
+		// move them every time
+		if self.start > 0 && self.start >= self.buffer.len() - self.start {
+			self.buffer.drain(..self.start);
+			self.offset += self.start as u64;
+			self.start = 0;
+		}
+		self.buffer.extend_from_slice(chunk);
+
+		// Matches that start in the last `overlap` bytes are scanned for with the next chunk
+		let done = self.buffer.len().saturating_sub(self.overlap);
+		if done > self.start {
+			self.pattern.scan_stream(&self.buffer[self.start..], self.offset + self.start as u64, done - self.start, &mut found);
+			self.start = done;
+		}
+	}
+
+	/// Scans the rest of the data, which is followed by `skip` bytes that can't be read before more data is fed
+	pub(crate) fn finish_with(&mut self, skip: u64, mut found: impl FnMut(P::Match)) {
+		self.pattern.scan_stream(&self.buffer[self.start..], self.offset + self.start as u64, self.buffer.len() - self.start, &mut found);
+		self.offset += self.buffer.len() as u64 + skip;
+		self.buffer.clear();
+		self.start = 0;
+	}
+}
+impl<P: StreamPattern + ?Sized> std::fmt::Debug for StreamScanner<'_, P> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("StreamScanner").field("position", &self.position()).field("buffered", &(self.buffer.len() - self.start)).finish()
+	}
+}
@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-use crate::{SigScan, Signature, SignatureParseError, signature};
+use crate::{Address, Arch, FileScanner, GenerateError, ModuleSelector, ParallelScanner, ResolveError, SetMatch, SigByte, SigElement, SigMatch, SigScan, Signature, SignatureParseError, SignatureFormat, SignatureParseErrorKind, SignatureGenerator, SignatureSet, StreamMatch, StreamScanner, UniqueScanError, signature};
 
 #[test]
 fn test_signature() {
@@ -22,6 +22,22 @@ fn test_sigscan() {
 	assert_eq!(bytes.sigscan(&signature).unwrap(), 4);
 }
 
+#[test]
+fn test_sigscan_all() {
+	let signature = Signature::from_str("AA ?? AA").unwrap();
+	let bytes: &[u8] = &[0xAA, 0x00, 0xAA, 0x01, 0xAA, 0x02, 0xAA, 0xAA, 0xAA];
+	assert_eq!(bytes.sigscan_all(&signature).collect::<Vec<_>>(), vec![0, 2, 4, 6]);
+	assert_eq!(signature.scan_all(bytes).non_overlapping().collect::<Vec<_>>(), vec![0, 4]);
+}
+
+#[test]
+fn test_sigscan_all_fail() {
+	let signature = Signature::from_str("AA BB").unwrap();
+	let bytes: &[u8] = &[0xAA, 0xAA, 0xBA, 0xAA];
+	assert_eq!(bytes.sigscan_all(&signature).next(), None);
+	assert_eq!(Signature::default().scan_all(bytes).next(), None);
+}
+
 #[test]
 fn test_sigscan_fail() {
 	let signature = Signature::from_str("00").unwrap();
@@ -37,7 +53,11 @@ fn test_sigscan_fail() {
 fn test_sigscan_invalid() {
 	assert_eq!(
 		Signature::from_str("??        32 ?? 123 ").unwrap_err(),
-		SignatureParseError::InvalidByte
+		SignatureParseError::InvalidByte { position: 3, token: String::new() }
+	);
+	assert_eq!(
+		Signature::from_str("  ?? 32 ?? 123 ").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 11, token: "123".to_string() }
 	);
 }
 
@@ -53,7 +73,12 @@ fn test_only_any_error() {
 fn test_invalid_byte_error() {
 	assert_eq!(
 		Signature::from_str("LL").unwrap_err(),
-		SignatureParseError::InvalidByte
+		SignatureParseError::InvalidByte { position: 0, token: "LL".to_string() }
+	);
+	assert_eq!(Signature::from_str("FF LL").unwrap_err(), SignatureParseErrorKind::InvalidByte);
+	assert_eq!(
+		Signature::from_str("FF LL").unwrap_err().to_string(),
+		"invalid byte `LL` at position 3 in signature"
 	);
 }
 
@@ -178,6 +203,33 @@ fn test_ptr_scan_fail() {
 	}
 }
 
+/// A pointer that only implements the required methods of `SigscanPtr`, so it is scanned through a copy of the memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct IndexPtr<'a>(&'a [u8], usize);
+impl crate::SigscanPtr for IndexPtr<'_> {
+	unsafe fn next(self) -> Self {
+		IndexPtr(self.0, self.1 + 1)
+	}
+	unsafe fn byte(self) -> u8 {
+		self.0[self.1]
+	}
+	unsafe fn rewind(self, bytes: usize) -> Self {
+		IndexPtr(self.0, self.1 - bytes)
+	}
+}
+
+#[test]
+fn test_ptr_scan_custom() {
+	let bytes = [0x55, 0x8B, 0xEC, 0x8B, 0xEC, 0x90];
+	let signature = signature!("8B EC");
+	unsafe {
+		assert_eq!(signature.scan_ptr(IndexPtr(&bytes, 0), IndexPtr(&bytes, 6)).map(|ptr| ptr.1), Some(1));
+		assert_eq!(signature.scan_ptr(IndexPtr(&bytes, 2), IndexPtr(&bytes, 6)).map(|ptr| ptr.1), Some(3));
+		assert_eq!(signature.scan_ptr(IndexPtr(&bytes, 0), IndexPtr(&bytes, 2)).map(|ptr| ptr.1), None);
+		assert_eq!(signature.scan_ptr_unique(IndexPtr(&bytes, 0), IndexPtr(&bytes, 6)), Err(UniqueScanError::MultipleFound { offsets: vec![1, 3] }));
+	}
+}
+
 #[test]
 fn test_proc_macro() {
 	assert_eq!(
@@ -186,6 +238,342 @@ fn test_proc_macro() {
 	);
 }
 
+#[test]
+fn test_nibble_wildcards() {
+	let signature = Signature::from_str("8B 4? ?8 40&F8").unwrap();
+	assert_eq!(
+		signature,
+		Signature::from(vec![SigByte::exact(0x8B), SigByte::masked(0x40, 0xF0), SigByte::masked(0x08, 0x0F), SigByte::masked(0x40, 0xF8)])
+	);
+	assert_eq!(signature, signature!("8B 4? ?8 40&F8"));
+	assert_eq!(format!("{:?}", signature), "8B 4? ?8 40&F8");
+
+	let bytes: &[u8] = &[0x8B, 0x3F, 0x08, 0x40, 0x8B, 0x4D, 0xF8, 0x47, 0x8B, 0x4D, 0xF8, 0x48];
+	assert_eq!(signature.scan_all(bytes).collect::<Vec<_>>(), vec![4]);
+
+	assert_eq!(signature.to_string_as(SignatureFormat::Ida).as_deref(), Some("8B 4? ?8 40&F8"));
+	assert_eq!(signature.to_string_as(SignatureFormat::X64dbg), None);
+	assert_eq!(signature.to_string_as(SignatureFormat::Code), None);
+	assert_eq!(signature!("8B 4? ?8").to_string_as(SignatureFormat::IdaCompact).as_deref(), Some("8B4??8"));
+	assert_eq!(Signature::parse_as(SignatureFormat::IdaCompact, "8B4??8").unwrap(), signature!("8B 4? ?8"));
+
+	assert_eq!(
+		Signature::from_str("8B 41&F0").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 3, token: "41&F0".to_string() }
+	);
+	assert_eq!(Signature::from_str("?? ?0&00").unwrap_err(), SignatureParseErrorKind::InvalidByte);
+}
+
+#[test]
+fn test_gaps() {
+	let signature = Signature::from_str("55 8B EC [2-6] E8 ?? ?? ?? ??").unwrap();
+	assert_eq!(signature, signature!("55 8B EC [2-6] E8 ?? ?? ?? ??"));
+	assert_eq!(signature, Signature::from_str("55 8B EC *{2,6} E8 ?? ?? ?? ??").unwrap());
+	assert_eq!(signature[3], SigElement::Gap { min: 2, max: 6 });
+	assert_eq!((signature.min_len(), signature.max_len()), (10, 14));
+	assert_eq!(Signature::from_str("AA [4] BB").unwrap(), signature!("AA *{4} BB"));
+
+	let bytes: &[u8] = &[
+		0x55, 0x8B, 0xEC, 0x90, 0x90, 0xE8, 0x01, 0x02, 0x03, 0x04, 0x00, 0x55, 0x8B, 0xEC, 0x90, 0x90, 0x90, 0x90, 0xE8, 0x01, 0x02, 0x03, 0x04,
+	];
+	assert_eq!(
+		signature.scan_match(bytes),
+		Some(SigMatch {
+			offset: 0,
+			bytes: &bytes[..10],
+			gaps: vec![2],
+			captures: Vec::new(),
+		})
+	);
+	assert_eq!(
+		signature.scan_all(bytes).matches().map(|found| (found.offset, found.gaps)).collect::<Vec<_>>(),
+		vec![(0, vec![2]), (11, vec![4])]
+	);
+	assert_eq!(signature!("55 8B EC [0-1] E8").scan(bytes), None);
+
+	assert_eq!(signature.to_string_as(SignatureFormat::Ida).as_deref(), Some("55 8B EC [2-6] E8 ? ? ? ?"));
+	assert_eq!(signature.to_string_as(SignatureFormat::X64dbg), None);
+	assert_eq!(signature.to_string_as(SignatureFormat::Code), None);
+
+	for invalid in ["[6-2]", "[a-b]", "[-2]", "[+2]", "*{1-2}", "[2"].iter() {
+		assert_eq!(
+			Signature::from_str(&format!("AA {}", invalid)).unwrap_err(),
+			SignatureParseError::InvalidByte { position: 3, token: invalid.to_string() }
+		);
+	}
+	assert_eq!(Signature::from_str("?? [1-2]").unwrap_err(), SignatureParseError::OnlyAny);
+}
+
+#[test]
+fn test_alternatives() {
+	let signature = Signature::from_str("85 C0 (74|75) ?? (8B 45 08 | 8B 4D 08)").unwrap();
+	assert_eq!(signature, signature!("85 C0 (74|75) ?? (8B 45 08 | 8B 4D 08)"));
+	assert_eq!(
+		signature[2],
+		SigElement::Alt(vec![signature!("74"), signature!("75")])
+	);
+	assert_eq!(signature[4], SigElement::Alt(vec![signature!("8B 45 08"), signature!("8B 4D 08")]));
+	assert_eq!(format!("{:?}", signature), "85 C0 (74|75) ?? (8B 45 08 | 8B 4D 08)");
+	assert_eq!(Signature::from_str("85 C0 ( 74 | 75 ) ??").unwrap(), signature!("85 C0 (74|75) ??"));
+
+	let bytes: &[u8] = &[0x85, 0xC0, 0x74, 0x10, 0x8B, 0x4D, 0x08, 0x85, 0xC0, 0x76, 0x10, 0x8B, 0x45, 0x08, 0x85, 0xC0, 0x75, 0x10, 0x8B, 0x45, 0x08];
+	assert_eq!(signature.scan_all(bytes).collect::<Vec<_>>(), vec![0, 14]);
+
+	// The first alternative matches on its own, but only the second leaves the rest of the signature matching
+	let signature = signature!("(AA | AA BB) CC");
+	assert_eq!(signature.scan_match(&[0xAA, 0xBB, 0xCC]).map(|found| found.len()), Some(3));
+	assert_eq!((signature.min_len(), signature.max_len()), (2, 3));
+
+	// Gaps in alternatives are reported in the order they were used
+	let signature = signature!("AA ((BB|[1]) [0-2] CC | DD) [1-2] EE");
+	let found = signature.scan_match(&[0xAA, 0x00, 0x01, 0xCC, 0x02, 0xEE]).unwrap();
+	assert_eq!((found.len(), found.gaps), (6, vec![1, 1, 1]));
+
+	assert_eq!(signature!("(74|75) ?? (E8|E9)").to_string_as(SignatureFormat::Ida).as_deref(), Some("(74|75) ? (E8|E9)"));
+	assert_eq!(signature!("(74|75) ?? (E8|E9)").to_string_as(SignatureFormat::X64dbg), None);
+	assert_eq!(signature!("(74|75) ?? (E8|E9)").to_string_as(SignatureFormat::Array), None);
+}
+
+#[test]
+fn test_alternative_errors() {
+	assert_eq!(Signature::from_str("85 (74|75").unwrap_err(), SignatureParseError::UnbalancedGroup { position: 3 });
+	assert_eq!(Signature::from_str("85 74)").unwrap_err(), SignatureParseError::UnbalancedGroup { position: 5 });
+	assert_eq!(Signature::from_str("85 )").unwrap_err(), SignatureParseError::UnbalancedGroup { position: 3 });
+	assert_eq!(
+		Signature::from_str("85 74|75").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 5, token: "|".to_string() }
+	);
+	assert_eq!(
+		Signature::from_str("85 (74||75)").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 7, token: String::new() }
+	);
+	assert_eq!(
+		Signature::from_str("85 (74|7G)").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 7, token: "7G".to_string() }
+	);
+	assert_eq!(Signature::from_str("(??|?? ??)").unwrap_err(), SignatureParseError::OnlyAny);
+	assert_eq!(
+		Signature::from_str("85 (74").unwrap_err().to_string(),
+		"unbalanced parenthesis at position 3 in signature"
+	);
+}
+
+#[test]
+fn test_captures() {
+	let signature = Signature::from_str("E8 ${'call:4} 48 8B ${'disp} 05 ${'rip:4}").unwrap();
+	assert_eq!(signature, signature!("E8 ${'call:4} 48 8B ${'disp} 05 ${'rip:4}"));
+	assert_eq!(signature[1], SigElement::Capture { name: "call".to_string(), len: 4 });
+	assert_eq!(format!("{:?}", signature), "E8 ${'call:4} 48 8B ${'disp} 05 ${'rip:4}");
+	assert_eq!(signature.len(), 7);
+	assert_eq!(signature.min_len(), 12);
+
+	let bytes: &[u8] = &[0x90, 0xE8, 0x10, 0x20, 0x30, 0x40, 0x48, 0x8B, 0x05, 0x01, 0x02, 0x03, 0x04, 0xC3];
+	let found = signature.scan_match(bytes).unwrap();
+	assert_eq!(found.offset, 1);
+	assert_eq!(found.captures.iter().map(|capture| capture.name).collect::<Vec<_>>(), vec!["call", "disp", "rip"]);
+
+	let call = found.capture("call").unwrap();
+	assert_eq!((call.offset, call.bytes), (2, &[0x10, 0x20, 0x30, 0x40][..]));
+	assert_eq!(call.address(), bytes[2..].as_ptr() as usize);
+	let disp = found.capture("disp").unwrap();
+	assert_eq!((disp.offset, disp.bytes), (8, &[][..]));
+	assert_eq!(found.capture("rip").unwrap().bytes, &[0x01, 0x02, 0x03, 0x04]);
+	assert!(found.capture("missing").is_none());
+
+	// Only captures in the alternative that matched are reported, and alternatives may reuse names
+	let signature = signature!("(E8 ${'target:4} | EB ${'target:1}) C3");
+	let found = signature.scan_match(&[0xEB, 0x7F, 0xC3]).unwrap();
+	assert_eq!(found.captures.len(), 1);
+	assert_eq!(found.capture("target").unwrap().bytes, &[0x7F]);
+
+	assert_eq!(signature!("E8 ${'rel:4}").to_string_as(SignatureFormat::Ida).as_deref(), Some("E8 ${'rel:4}"));
+	assert_eq!(signature!("E8 ${'rel:4}").to_string_as(SignatureFormat::Code), None);
+}
+
+#[test]
+fn test_capture_errors() {
+	for invalid in ["${'}", "${'rel:}", "${'rel:x}", "${'re-l:4}", "${rel:4}", "${'rel:4"].iter() {
+		assert_eq!(
+			Signature::from_str(&format!("E8 {}", invalid)).unwrap_err(),
+			SignatureParseError::InvalidByte { position: 3, token: invalid.to_string() }
+		);
+	}
+	assert_eq!(
+		Signature::from_str("E8 ${'rel:4} ${'rel}").unwrap_err(),
+		SignatureParseError::DuplicateCapture { position: 13, name: "rel".to_string() }
+	);
+	assert_eq!(
+		Signature::from_str("(E8 ${'rel:4} | E9 ${'rel:4}) ${'rel:1}").unwrap_err(),
+		SignatureParseError::DuplicateCapture { position: 30, name: "rel".to_string() }
+	);
+	assert_eq!(Signature::from_str("${'rel:4}").unwrap_err(), SignatureParseError::OnlyAny);
+}
+
+#[test]
+fn test_address_resolution() {
+	#[rustfmt::skip]
+	let bytes: &[u8] = &[
+		// 0: call +0x5 (-> 10)
+		0xE8, 0x05, 0x00, 0x00, 0x00,
+		// 5: jmp short -0x7 (-> 0)
+		0xEB, 0xF9,
+		0xCC, 0xCC, 0xCC,
+		// 10: lea rax, [rip+0x1] (-> 18)
+		0x48, 0x8D, 0x05, 0x01, 0x00, 0x00, 0x00,
+		0xC3,
+		// 18: a value
+		0x78, 0x56, 0x34, 0x12,
+	];
+	let call = signature!("E8 ? ? ? ? EB").scan_address(bytes).unwrap();
+	assert_eq!(call.get(), bytes.as_ptr() as usize);
+	let value = call.clone().rel32(1, 5).unwrap().rel32(3, 7).unwrap();
+	assert_eq!(value.offset(), 18);
+	assert_eq!(value.deref::<u32>(), Ok(0x12345678));
+	assert_eq!(value.deref::<[u8; 2]>(), Ok([0x78, 0x56]));
+	assert_eq!(value.clone().sub(1).unwrap().deref::<u8>(), Ok(0xC3));
+
+	let jmp = signature!("EB ?").scan_address(bytes).unwrap();
+	assert_eq!(jmp.offset(), 5);
+	assert_eq!(jmp.rel8(1, 2).unwrap().offset(), 0);
+
+	// Nothing can be read or followed outside of the slice
+	let end = bytes.as_ptr() as usize + bytes.len();
+	assert_eq!(value.deref::<u64>(), Err(ResolveError::OutOfBounds { address: value.get(), len: 8 }));
+	assert_eq!(value.clone().add(4).unwrap_err(), ResolveError::OutOfBounds { address: end, len: 0 });
+	assert_eq!(call.clone().sub(1).unwrap_err(), ResolveError::OutOfBounds { address: call.get() - 1, len: 0 });
+	assert_eq!(call.clone().rel32(1, 30).unwrap_err(), ResolveError::OutOfBounds { address: call.get() + 30 + 5, len: 0 });
+	assert_eq!(value.clone().rel32(0, 4).unwrap_err(), ResolveError::OutOfBounds { address: value.get() + 4 + 0x12345678, len: 0 });
+	assert!(value.deref_ptr().is_err());
+	assert!(Address::in_slice(bytes, bytes.len()).is_err());
+
+	// A match that is empty at the end of the slice has no address in it
+	let empty = Signature::from(vec![SigElement::Gap { min: 0, max: 0 }]);
+	assert_eq!(empty.scan(&[]), Some(0));
+	assert!(empty.scan_address(&[]).is_none());
+
+	// Captures can be followed too
+	let signature = signature!("48 8D 05 ${'rip:4} C3");
+	let found = signature.scan_match(bytes).unwrap();
+	let rip = Address::in_slice(bytes, 0).unwrap().at(found.capture("rip").unwrap().address()).unwrap();
+	assert_eq!(rip.rel32(0, 4).unwrap().deref::<u32>(), Ok(0x12345678));
+}
+
+#[test]
+fn test_x86_decoder() {
+	use crate::x86::{decode, Operand, OperandKind};
+
+	let operand = |offset, len, kind| Some(Operand { offset, len, kind });
+	let x64: &[(&[u8], usize)] = &[
+		(&[0x55], 1),
+		(&[0x48, 0x89, 0x5C, 0x24, 0x08], 5),
+		(&[0x0F, 0x84, 0x10, 0x00, 0x00, 0x00], 6),
+		(&[0x74, 0x10], 2),
+		(&[0x66, 0xC7, 0x45, 0xF8, 0x34, 0x12], 6),
+		(&[0xC7, 0x44, 0x24, 0x04, 0x78, 0x56, 0x34, 0x12], 8),
+		(&[0xF6, 0xC1, 0x01], 3),
+		(&[0xF7, 0xD8], 2),
+		(&[0xC5, 0xF8, 0x77], 3),
+		(&[0xC4, 0xE3, 0x7D, 0x18, 0xC1, 0x01], 6),
+		(&[0x0F, 0x1F, 0x44, 0x00, 0x00], 5),
+		(&[0x66, 0x0F, 0x3A, 0x0F, 0xC1, 0x08], 6),
+		(&[0xF3, 0x0F, 0x1E, 0xFA], 4),
+		(&[0xC8, 0x10, 0x00, 0x00], 4),
+		(&[0xA1, 1, 2, 3, 4, 5, 6, 7, 8], 9),
+	];
+	for (code, len) in x64 {
+		assert_eq!(decode(code, Arch::X86_64).map(|instruction| instruction.len), Some(*len), "{:02X?}", code);
+	}
+
+	let call = decode(&[0xE8, 0x10, 0x00, 0x00, 0x00], Arch::X86_64).unwrap();
+	assert_eq!((call.len, call.displacement, call.immediate), (5, None, operand(1, 4, OperandKind::Relative)));
+	let lea = decode(&[0x48, 0x8D, 0x05, 0x11, 0x22, 0x33, 0x44], Arch::X86_64).unwrap();
+	assert_eq!((lea.len, lea.displacement, lea.immediate), (7, operand(3, 4, OperandKind::RipRelative), None));
+	let movabs = decode(&[0x48, 0xB8, 1, 2, 3, 4, 5, 6, 7, 8], Arch::X86_64).unwrap();
+	assert_eq!((movabs.len, movabs.immediate), (10, operand(2, 8, OperandKind::Immediate)));
+	let evex = decode(&[0x62, 0xF1, 0x7C, 0x48, 0x10, 0x05, 0x00, 0x01, 0x00, 0x00], Arch::X86_64).unwrap();
+	assert_eq!((evex.len, evex.displacement), (10, operand(6, 4, OperandKind::RipRelative)));
+
+	let x86: &[(&[u8], usize)] = &[(&[0x40], 1), (&[0x66, 0xE8, 0x10, 0x00], 4), (&[0x67, 0x8B, 0x46, 0x08], 4), (&[0x62, 0x01], 2)];
+	for (code, len) in x86 {
+		assert_eq!(decode(code, Arch::X86).map(|instruction| instruction.len), Some(*len), "{:02X?}", code);
+	}
+	let absolute = decode(&[0x8B, 0x0D, 0x78, 0x56, 0x34, 0x12], Arch::X86).unwrap();
+	assert_eq!((absolute.len, absolute.displacement), (6, operand(2, 4, OperandKind::Absolute)));
+	let table = decode(&[0xFF, 0x24, 0x85, 0x78, 0x56, 0x34, 0x12], Arch::X86).unwrap();
+	assert_eq!((table.len, table.displacement), (7, operand(3, 4, OperandKind::Absolute)));
+
+	// Invalid in 64-bit mode, or cut off
+	assert_eq!(decode(&[0x06], Arch::X86_64), None);
+	assert_eq!(decode(&[0xE8, 0x00], Arch::X86_64), None);
+	assert_eq!(decode(&[0x66, 0x66], Arch::X86_64), None);
+}
+
+#[test]
+fn test_signature_generator() {
+	#[rustfmt::skip]
+	let bytes: &[u8] = &[
+		// 0: push rbp; mov rbp, rsp; call rel32; mov rax, [rip+disp32]; pop rbp; ret
+		0x55, 0x48, 0x89, 0xE5, 0xE8, 0x10, 0x00, 0x00, 0x00, 0x48, 0x8B, 0x05, 0x20, 0x00, 0x00, 0x00, 0x5D, 0xC3,
+		// 18: the same, but pop rcx
+		0x55, 0x48, 0x89, 0xE5, 0xE8, 0x30, 0x00, 0x00, 0x00, 0x48, 0x8B, 0x05, 0x40, 0x00, 0x00, 0x00, 0x59, 0xC3,
+		// 36: push rbp; mov rbp, rsp; call rel32; xor eax, eax; pop rbp; ret
+		0x55, 0x48, 0x89, 0xE5, 0xE8, 0x50, 0x00, 0x00, 0x00, 0x31, 0xC0, 0x5D, 0xC3,
+		// 49: push 0x401000; movabs rax, 0x1122334455667788; ret
+		0x68, 0x00, 0x10, 0x40, 0x00, 0x48, 0xB8, 0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0xC3,
+		// 65: push 0x402000; ret
+		0x68, 0x00, 0x20, 0x40, 0x00, 0xC3,
+	];
+	let generator = SignatureGenerator::new().arch(Arch::X86_64);
+
+	let signature = generator.generate(bytes, 0).unwrap();
+	assert_eq!(signature, signature!("55 48 89 E5 E8 ? ? ? ? 48 8B 05 ? ? ? ? 5D"));
+	assert_eq!(signature.scan_all(bytes).collect::<Vec<_>>(), vec![0]);
+	assert_eq!(signature.to_string_as(SignatureFormat::Ida).as_deref(), Some("55 48 89 E5 E8 ? ? ? ? 48 8B 05 ? ? ? ? 5D"));
+	assert_eq!(generator.generate(bytes, 18).unwrap(), signature!("55 48 89 E5 E8 ? ? ? ? 48 8B 05 ? ? ? ? 59"));
+	assert_eq!(generator.generate(bytes, 36).unwrap(), signature!("55 48 89 E5 E8 ? ? ? ? 31"));
+
+	// A unique first instruction is shortened to its shortest unique prefix
+	assert_eq!(generator.generate(bytes, 54).unwrap(), signature!("48 B8"));
+
+	// Immediates are only wildcarded if they look like addresses
+	assert_eq!(generator.generate(bytes, 49).unwrap(), signature!("68 00 10"));
+	assert_eq!(generator.clone().address_range(0x400000..0x500000).generate(bytes, 49).unwrap(), signature!("68 ? ? ? ? 48"));
+
+	assert_eq!(generator.clone().max_len(12).generate(bytes, 0), Err(GenerateError::NotUnique { len: 12, matches: 2 }));
+	assert_eq!(generator.generate(&bytes[..18].repeat(2), 19), Err(GenerateError::NotUnique { len: 17, matches: 2 }));
+	assert_eq!(generator.generate(bytes, bytes.len()), Err(GenerateError::OutOfBounds { target: bytes.len() }));
+	assert_eq!(generator.generate(&[0x55, 0x06, 0x55], 0), Err(GenerateError::InvalidInstruction { at: 1 }));
+}
+
+#[test]
+fn test_signature_set_alternatives() {
+	let set = SignatureSet::new(vec![signature!("(E8|E9) ?? C3"), signature!("C3 (CC|90)")]);
+	let bytes: &[u8] = &[0xE9, 0x00, 0xC3, 0x90, 0xE8, 0x01, 0xC3];
+	assert_eq!(set.scan_all(bytes), vec![SetMatch { offset: 0, id: 0 }, SetMatch { offset: 2, id: 1 }, SetMatch { offset: 4, id: 0 }]);
+}
+
+#[test]
+fn test_signature_set_gaps() {
+	let set = SignatureSet::new(vec![signature!("E8 [0-2] C3"), signature!("[1] C3"), signature!("CC")]);
+	let bytes: &[u8] = &[0xE8, 0x00, 0xC3, 0xE8, 0xC3];
+	assert_eq!(set.scan(bytes), vec![Some(0), Some(1), None]);
+	assert_eq!(
+		set.scan_all(bytes),
+		vec![
+			SetMatch { offset: 0, id: 0 },
+			SetMatch { offset: 1, id: 1 },
+			SetMatch { offset: 3, id: 0 },
+			SetMatch { offset: 3, id: 1 },
+		]
+	);
+}
+
+#[test]
+fn test_option_from_impls() {
+	assert_eq!(Signature::from(vec![Some(0x55), None]), signature!("55 ??"));
+	assert_eq!(Signature::from(&[0x55u8, 0x8B][..]), signature!("55 8B"));
+}
+
 #[test]
 fn test_mixed_any() {
 	Signature::from_str("FF 0E EE 00 ?? ? 0A ?").unwrap();
@@ -236,3 +624,519 @@ fn test_obfstr_proc_macro() {
 		crate::obfsignature!("FF 0E EE 00 ?? ?? 0A")
 	);
 }
+
+#[test]
+fn test_sigscan_partial_overlap() {
+	let signature = Signature::from_str("AA AA BB").unwrap();
+	let bytes: &[u8] = &[0xAA, 0xAA, 0xAA, 0xBB];
+	assert_eq!(signature.scan(bytes), Some(1));
+	unsafe {
+		assert_eq!(
+			signature.scan_ptr(bytes.as_ptr(), bytes.as_ptr().add(bytes.len())),
+			Some(bytes.as_ptr().add(1))
+		);
+	}
+}
+
+#[test]
+fn test_sigscan_any_at_start() {
+	let signature = Signature::from_str("?? BB").unwrap();
+	let bytes: &[u8] = &[0xAA, 0xBB, 0xBB];
+	assert_eq!(signature.scan(bytes), Some(0));
+	assert_eq!(signature.scan_all(bytes).collect::<Vec<_>>(), vec![0, 1]);
+}
+
+/// xorshift64*, good enough to generate haystacks for the property tests
+struct Rng(u64);
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 >> 12;
+		self.0 ^= self.0 << 25;
+		self.0 ^= self.0 >> 27;
+		self.0.wrapping_mul(0x2545F4914F6CDD1D)
+	}
+
+	fn below(&mut self, n: usize) -> usize {
+		(self.next() % n as u64) as usize
+	}
+
+	/// Picks from a tiny alphabet so that partial matches are very common
+	fn byte(&mut self) -> u8 {
+		[0xAA, 0xBB, 0xCC][self.below(3)]
+	}
+
+	/// Mostly exact bytes, with some full and partial wildcards
+	fn sig_byte(&mut self) -> SigByte {
+		match self.below(8) {
+			0 | 1 => SigByte::ANY,
+			2 => {
+				let mask = [0xF0, 0x0F, 0x33][self.below(3)];
+				SigByte::masked(self.byte(), mask)
+			}
+			_ => SigByte::exact(self.byte()),
+		}
+	}
+
+	/// Mostly bytes, with some short gaps and alternatives
+	fn element(&mut self) -> SigElement {
+		match self.below(20) {
+			0 | 1 => {
+				let min = self.below(3);
+				SigElement::Gap { min, max: min + self.below(3) }
+			}
+			2 => SigElement::Alt(
+				(0..self.below(3) + 1)
+					.map(|_| (0..self.below(3) + 1).map(|_| SigElement::Byte(self.sig_byte())).collect::<Vec<_>>().into())
+					.collect(),
+			),
+			_ => SigElement::Byte(self.sig_byte()),
+		}
+	}
+}
+
+/// Brute-force reference: every offset at which every element of the signature is satisfied, trying every length of every gap
+fn reference_scan_all(signature: &Signature, bytes: &[u8]) -> Vec<usize> {
+	fn matches(signature: &[SigElement], bytes: &[u8]) -> bool {
+		match signature.split_first() {
+			None => true,
+			Some((SigElement::Byte(sig_byte), rest)) => bytes.first().map(|byte| sig_byte.matches(*byte)).unwrap_or(false) && matches(rest, &bytes[1..]),
+			Some((SigElement::Gap { min, max }, rest)) => (*min..=*max).any(|len| len <= bytes.len() && matches(rest, &bytes[len..])),
+			Some((SigElement::Capture { len, .. }, rest)) => *len <= bytes.len() && matches(rest, &bytes[*len..]),
+			Some((SigElement::Alt(alternatives), rest)) => alternatives.iter().any(|alternative| {
+				let mut signature = alternative.to_vec();
+				signature.extend_from_slice(rest);
+				matches(&signature, bytes)
+			}),
+		}
+	}
+	if signature.is_empty() {
+		return Vec::new();
+	}
+	(0..=bytes.len()).filter(|&pos| matches(signature, &bytes[pos..])).collect()
+}
+
+#[test]
+fn test_sigscan_matches_reference() {
+	let mut rng = Rng(0x5EED_5CA1_AB1E_F00D);
+	for _ in 0..20000 {
+		let bytes: Vec<u8> = (0..rng.below(24)).map(|_| rng.byte()).collect();
+		let signature: Signature = (0..rng.below(5) + 1)
+			.map(|_| rng.element())
+			.collect::<Vec<_>>()
+			.into();
+
+		let expected = reference_scan_all(&signature, &bytes);
+		assert_eq!(signature.scan(&bytes), expected.first().copied(), "{:?} in {:02X?}", signature, bytes);
+		assert_eq!(signature.scan_all(&bytes).collect::<Vec<_>>(), expected, "{:?} in {:02X?}", signature, bytes);
+		// Which alternative matched isn't reported, so only signatures without them can be checked like this
+		let has_alternatives = signature.iter().any(|element| matches!(element, SigElement::Alt(_)));
+		for found in signature.scan_all(&bytes).matches().filter(|_| !has_alternatives) {
+			// Replacing each gap with as many `??` as it matched must give a signature that matches the same bytes
+			let mut gaps = found.gaps.iter();
+			let fixed: Signature = signature
+				.iter()
+				.flat_map(|element| match element {
+					SigElement::Gap { .. } => vec![SigElement::Byte(SigByte::ANY); *gaps.next().unwrap()],
+					SigElement::Capture { len, .. } => vec![SigElement::Byte(SigByte::ANY); *len],
+					element => vec![element.clone()],
+				})
+				.collect::<Vec<_>>()
+				.into();
+			assert_eq!(gaps.next(), None);
+			assert_eq!(fixed.len(), found.len(), "{:?} in {:02X?}", signature, bytes);
+			assert!(
+				fixed.iter().zip(&bytes[found.offset..]).all(|(element, byte)| element.as_byte().unwrap().matches(*byte)),
+				"{:?} in {:02X?}",
+				signature,
+				bytes
+			);
+		}
+		unsafe {
+			assert_eq!(
+				signature.scan_ptr(bytes.as_ptr(), bytes.as_ptr().add(bytes.len())),
+				expected.first().map(|&pos| bytes.as_ptr().add(pos)),
+				"{:?} in {:02X?}",
+				signature,
+				bytes
+			);
+		}
+	}
+}
+
+#[test]
+fn test_sigscan_long_matches_reference() {
+	let mut rng = Rng(0xDEC0_DED0_0BAD_CAFE);
+	for _ in 0..2000 {
+		// Long enough to cross several SIMD lanes, with a sparse alphabet so anchors are rare
+		let bytes: Vec<u8> = (0..rng.below(300)).map(|_| if rng.below(8) == 0 { rng.byte() } else { 0 }).collect();
+		let signature: Signature = (0..rng.below(4) + 1)
+			.map(|_| rng.element())
+			.collect::<Vec<_>>()
+			.into();
+
+		let expected = reference_scan_all(&signature, &bytes);
+		assert_eq!(signature.scan_all(&bytes).collect::<Vec<_>>(), expected, "{:?} in {:02X?}", signature, bytes);
+	}
+}
+
+#[test]
+fn test_memchr() {
+	let mut rng = Rng(0x0123_4567_89AB_CDEF);
+	for _ in 0..2000 {
+		let bytes: Vec<u8> = (0..rng.below(200)).map(|_| if rng.below(16) == 0 { rng.byte() } else { 0 }).collect();
+		let needle = rng.byte();
+		assert_eq!(crate::matcher::memchr(needle, &bytes), crate::matcher::memchr_scalar(needle, &bytes));
+	}
+}
+
+#[test]
+fn test_signature_set() {
+	let set: SignatureSet = vec![
+		Signature::from_str("8B 45 ?? 89").unwrap(),
+		Signature::from_str("45 0C").unwrap(),
+		Signature::from_str("DE AD").unwrap(),
+		Signature::from_str("?? 45").unwrap(),
+	]
+	.into_iter()
+	.collect();
+	let bytes: &[u8] = &[0x8B, 0x45, 0x0C, 0x89, 0x8B, 0x45, 0x10, 0x89];
+	assert_eq!(set.scan(bytes), vec![Some(0), Some(1), None, Some(0)]);
+	assert_eq!(
+		set.scan_all(bytes),
+		vec![
+			SetMatch { offset: 0, id: 0 },
+			SetMatch { offset: 0, id: 3 },
+			SetMatch { offset: 1, id: 1 },
+			SetMatch { offset: 4, id: 0 },
+			SetMatch { offset: 4, id: 3 },
+		]
+	);
+}
+
+#[test]
+fn test_signature_set_matches_reference() {
+	let mut rng = Rng(0xFEED_FACE_CAFE_BEEF);
+	for _ in 0..500 {
+		let signatures: Vec<Signature> = (0..rng.below(8) + 1)
+			.map(|_| {
+				(0..rng.below(12) + 1)
+					.map(|_| rng.element())
+					.collect::<Vec<_>>()
+					.into()
+			})
+			.collect();
+		let set = SignatureSet::new(signatures.clone());
+		let bytes: Vec<u8> = (0..rng.below(200)).map(|_| rng.byte()).collect();
+
+		let mut expected: Vec<SetMatch> = signatures
+			.iter()
+			.enumerate()
+			.flat_map(|(id, signature)| reference_scan_all(signature, &bytes).into_iter().map(move |offset| SetMatch { offset, id }))
+			.collect();
+		expected.sort();
+		assert_eq!(set.scan_all(&bytes), expected);
+		assert_eq!(
+			set.scan(&bytes),
+			signatures.iter().map(|signature| reference_scan_all(signature, &bytes).first().copied()).collect::<Vec<_>>()
+		);
+	}
+}
+
+#[test]
+fn test_module_selector() {
+	let path = "/home/user/game/bin/libclient.so";
+	assert!(ModuleSelector::from(path).matches(path));
+	assert!(ModuleSelector::from("libclient.so").matches(path));
+	assert!(!ModuleSelector::from("client.so").matches(path));
+	assert!(!ModuleSelector::from("/bin/libclient.so").matches(path));
+
+	assert!(ModuleSelector::Prefix("libclient".to_string()).matches(path));
+	assert!(!ModuleSelector::Prefix("client".to_string()).matches(path));
+	assert!(ModuleSelector::Prefix("/home/user/".to_string()).matches(path));
+
+	assert!(ModuleSelector::Suffix("client.so".to_string()).matches(path));
+	assert!(ModuleSelector::Suffix("bin/libclient.so".to_string()).matches(path));
+	assert!(!ModuleSelector::Suffix("server.so".to_string()).matches(path));
+
+	assert!(ModuleSelector::Glob("lib*.so".to_string()).matches(path));
+	assert!(ModuleSelector::Glob("libclien?.so".to_string()).matches(path));
+	assert!(ModuleSelector::Glob("*/game/*/*.so".to_string()).matches(path));
+	assert!(!ModuleSelector::Glob("client*".to_string()).matches(path));
+	assert!(!ModuleSelector::Glob("lib*.dll".to_string()).matches(path));
+}
+
+#[test]
+fn test_scan_unique() {
+	let bytes: &[u8] = &[0xAA, 0xAA, 0xAA, 0xBB, 0xCC, 0xAA, 0xBB];
+	assert_eq!(signature!("AA BB CC").scan_unique(bytes), Ok(2));
+	assert_eq!(bytes.sigscan_unique(&signature!("CC")), Ok(4));
+	assert_eq!(signature!("DD").scan_unique(bytes), Err(UniqueScanError::NotFound));
+	assert_eq!(signature!("AA BB").scan_unique(bytes), Err(UniqueScanError::MultipleFound { offsets: vec![2, 5] }));
+
+	// Overlapping matches count, unless asked not to
+	let signature = signature!("AA AA");
+	assert_eq!(signature.scan_unique(bytes), Err(UniqueScanError::MultipleFound { offsets: vec![0, 1] }));
+	assert_eq!(signature.scan_all(bytes).non_overlapping().unique(), Ok(0));
+	assert_eq!(
+		signature!("AA ?").scan_unique(bytes).unwrap_err().to_string(),
+		"signature found 4 times, at offsets 0x0, 0x1, 0x2, 0x5"
+	);
+
+	unsafe {
+		let (start, end) = (bytes.as_ptr(), bytes.as_ptr().add(bytes.len()));
+		assert_eq!(signature!("AA BB CC").scan_ptr_unique(start, end), Ok(start.add(2)));
+		assert_eq!(signature!("AA BB").scan_ptr_unique(start.add(3), end), Ok(start.add(5)));
+		assert_eq!(signature!("AA BB").scan_ptr_unique(start, end), Err(UniqueScanError::MultipleFound { offsets: vec![2, 5] }));
+		assert_eq!(signature!("AA").scan_ptr_unique(end, start), Err(UniqueScanError::NotFound));
+	}
+}
+
+#[test]
+fn test_errors_box_dyn_error() {
+	fn parse(signature: &str) -> Result<Signature, Box<dyn std::error::Error + Send + Sync>> {
+		Ok(Signature::from_str(signature)?)
+	}
+	assert_eq!(parse("FF ?? 00").unwrap(), signature!("FF ?? 00"));
+	assert_eq!(parse("").unwrap_err().to_string(), "empty signature");
+
+	fn scan() -> Result<*mut u8, Box<dyn std::error::Error + Send + Sync>> {
+		Ok(unsafe { signature!("FF ?? 00").scan_module("not a loaded module.so")? })
+	}
+	assert_eq!(scan().unwrap_err().to_string(), "module `not a loaded module.so` is not loaded");
+}
+
+#[test]
+fn test_signature_formats() {
+	let signature = signature!("55 8B EC ?? 00");
+	let formatted = [
+		(SignatureFormat::Ida, "55 8B EC ? 00"),
+		(SignatureFormat::IdaCompact, "558BEC??00"),
+		(SignatureFormat::Code, "\\x55\\x8B\\xEC\\x00\\x00 xxx?x"),
+		(SignatureFormat::X64dbg, "55 8B EC ?? 00"),
+	];
+	for (format, s) in formatted.iter() {
+		assert_eq!(signature.to_string_as(*format).as_deref(), Some(*s));
+		assert_eq!(Signature::parse_as(*format, s).unwrap(), signature);
+	}
+	assert_eq!(signature.to_string_as(SignatureFormat::Array), None);
+
+	let fixed = signature!("55 8B EC 00");
+	assert_eq!(fixed.to_string_as(SignatureFormat::Array).as_deref(), Some("0x55, 0x8B, 0xEC, 0x00"));
+	assert_eq!(Signature::parse_as(SignatureFormat::Array, "0x55, 0x8B, 0xEC, 0x00").unwrap(), fixed);
+	assert_eq!(Signature::parse_as(SignatureFormat::Array, "{ 0x55, 0x8b, 0xEC, 0x0, }").unwrap(), fixed);
+	assert_eq!(Signature::parse_as(SignatureFormat::Array, "[0x55,0x8B,0xEC,0x00]").unwrap(), fixed);
+}
+
+#[test]
+fn test_signature_format_variants() {
+	let signature = signature!("55 8B EC ?? 00");
+	assert_eq!(Signature::parse_as(SignatureFormat::IdaCompact, "558B EC??00").unwrap(), signature);
+	assert_eq!(Signature::parse_as(SignatureFormat::Code, r#""\x55\x8B\xEC\x00\x00", "xxx?x""#).unwrap(), signature);
+	assert_eq!(Signature::from_code(r"\x55\x8B\xEC\xFF\x00", "xxx?x").unwrap(), signature);
+}
+
+#[test]
+fn test_signature_format_errors() {
+	assert_eq!(
+		Signature::parse_as(SignatureFormat::IdaCompact, "558BE").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 4, token: "E".to_string() }
+	);
+	assert_eq!(
+		Signature::parse_as(SignatureFormat::IdaCompact, "55ZZ").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 2, token: "ZZ".to_string() }
+	);
+	assert_eq!(
+		Signature::parse_as(SignatureFormat::Code, r"\x55\x8B xx?").unwrap_err(),
+		SignatureParseError::MaskLength { bytes: 2, mask: 3 }
+	);
+	assert_eq!(
+		Signature::parse_as(SignatureFormat::Code, r"\x55\x8G xx").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 6, token: "8G".to_string() }
+	);
+	assert_eq!(
+		Signature::parse_as(SignatureFormat::Code, r"\x55\x8B x!").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 10, token: "!".to_string() }
+	);
+	assert_eq!(Signature::parse_as(SignatureFormat::Code, r"\x55\x8B ??").unwrap_err(), SignatureParseError::OnlyAny);
+	assert_eq!(
+		Signature::parse_as(SignatureFormat::Array, "0x55, 55").unwrap_err(),
+		SignatureParseError::InvalidByte { position: 6, token: "55".to_string() }
+	);
+	assert_eq!(Signature::parse_as(SignatureFormat::Array, "{}").unwrap_err(), SignatureParseError::Empty);
+}
+
+#[test]
+fn test_scan_file() {
+	let mut data: Vec<u8> = (0..10000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+	for pos in [0, 1021, 4094, 4095, 8190, 9995].iter() {
+		data[*pos..*pos + 5].copy_from_slice(&[0xDE, 0xAD, 0x00, 0xBE, 0xEF]);
+	}
+	let signature = signature!("DE AD ? BE EF");
+	let expected: Vec<u64> = signature.scan_all(&data).map(|offset| offset as u64).collect();
+	assert_eq!(expected, vec![0, 1021, 4095, 8190, 9995]);
+
+	let path = std::env::temp_dir().join(format!("skidscan-test-scan-file-{}", std::process::id()));
+	std::fs::write(&path, &data).unwrap();
+	for chunk_size in [1, 3, 7, 4096, 1 << 20].iter() {
+		let scanner = FileScanner::open(&path).unwrap().chunk_size(*chunk_size);
+		assert_eq!(scanner.scan_all(&signature).unwrap(), expected, "chunk size {}", chunk_size);
+		assert_eq!(scanner.scan_all(&signature).unwrap(), expected, "chunk size {}, scanned again", chunk_size);
+	}
+	let scanner = unsafe { FileScanner::open(&path).unwrap().mmap(true) };
+	assert_eq!(scanner.scan_all(&signature).unwrap(), expected);
+
+	// Scanning doesn't move the file's cursor, so scans from multiple threads and other reads of the file don't interfere
+	let mut file = std::fs::File::open(&path).unwrap();
+	let mut start = [0; 10];
+	std::io::Read::read_exact(&mut file, &mut start).unwrap();
+	let scanner = FileScanner::new(file.try_clone().unwrap()).chunk_size(7);
+	std::thread::scope(|scope| {
+		for _ in 0..4 {
+			scope.spawn(|| assert_eq!(scanner.scan_all(&signature).unwrap(), expected));
+		}
+	});
+	let mut next = [0; 1];
+	std::io::Read::read_exact(&mut file, &mut next).unwrap();
+	assert_eq!(next[0], data[10]);
+
+	std::fs::write(&path, []).unwrap();
+	assert_eq!(unsafe { FileScanner::open(&path).unwrap().mmap(true) }.scan_all(&signature).unwrap(), vec![]);
+	assert_eq!(FileScanner::open(&path).unwrap().scan_all(&signature).unwrap(), vec![]);
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_stream_scanner() {
+	let mut data: Vec<u8> = (0..5000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+	for (pos, marker) in [(10, &[0xDE, 0xAD, 0x01, 0x02, 0xBE, 0xEF][..]), (2045, &[0xDE, 0xAD, 0xBE, 0xEF]), (4094, &[0xCA, 0xFE, 0xDE, 0xAD, 0x01, 0xBE, 0xEF])].iter() {
+		data[*pos..*pos + marker.len()].copy_from_slice(marker);
+	}
+
+	// Variable length matches span more chunks than the shortest match would
+	let signature = signature!("DE AD [0-2] BE EF");
+	let expected: Vec<u64> = signature.scan_all(&data).map(|offset| offset as u64).collect();
+	assert_eq!(expected, vec![10, 2045, 4096]);
+
+	let set = SignatureSet::new(vec![signature.clone(), signature!("CA FE"), signature!("BE EF")]);
+	let expected_set: Vec<StreamMatch> = set
+		.scan_all(&data)
+		.into_iter()
+		.map(|found| StreamMatch {
+			offset: found.offset as u64,
+			id: found.id,
+		})
+		.collect();
+	assert_eq!(expected_set.len(), 7);
+
+	for chunk_size in [1, 2, 5, 64, 4096, 10000].iter() {
+		let mut scanner = StreamScanner::new(&signature);
+		let mut found = Vec::new();
+		for chunk in data.chunks(*chunk_size) {
+			found.extend(scanner.feed(chunk));
+		}
+		found.extend(scanner.finish());
+		assert_eq!(found, expected, "chunk size {}", chunk_size);
+		assert_eq!(scanner.position(), data.len() as u64);
+
+		let mut scanner = StreamScanner::new(&set);
+		let mut found = Vec::new();
+		for chunk in data.chunks(*chunk_size) {
+			found.extend(scanner.feed(chunk));
+		}
+		found.extend(scanner.finish());
+		assert_eq!(found, expected_set, "chunk size {}", chunk_size);
+	}
+
+	assert_eq!(StreamScanner::new(&signature).scan_reader(&data[..]).unwrap(), expected);
+	assert_eq!(StreamScanner::new(&set).scan_reader(&data[..]).unwrap(), expected_set);
+
+	// Matches don't span the end of a finished stream
+	let mut scanner = StreamScanner::new(&signature);
+	assert_eq!(scanner.feed(&[0xDE, 0xAD]), vec![]);
+	assert_eq!(scanner.finish(), vec![]);
+	assert_eq!(scanner.feed(&[0xBE, 0xEF, 0xDE, 0xAD, 0xBE, 0xEF]), vec![]);
+	assert_eq!(scanner.finish(), vec![4]);
+
+	// At most 1 MiB is kept for patterns with unbounded gaps, so longer matches are only found within a chunk
+	let signature = Signature::from(vec![SigElement::Byte(SigByte::exact(0xAA)), SigElement::Gap { min: 0, max: usize::MAX }, SigElement::Byte(SigByte::exact(0xBB))]);
+	let mut data = vec![0; 3 << 20];
+	data[0] = 0xAA;
+	data[1000] = 0xBB;
+	data[1 << 20] = 0xAA;
+	data[(3 << 20) - 1] = 0xBB;
+	assert_eq!(signature.scan_all(&data).collect::<Vec<_>>(), vec![0, 1 << 20]);
+	let mut scanner = StreamScanner::new(&signature);
+	let mut found = Vec::new();
+	for chunk in data.chunks(64 << 10) {
+		found.extend(scanner.feed(chunk));
+	}
+	found.extend(scanner.finish());
+	assert_eq!(found, vec![0]);
+	assert_eq!(StreamScanner::new(&signature).scan_reader(&data[..]).unwrap(), vec![0]);
+
+	// Feeding a byte at a time only scans the new starts, instead of everything that's kept
+	let mut scanner = StreamScanner::new(&signature);
+	let mut found = Vec::new();
+	for chunk in data.chunks(1) {
+		found.extend(scanner.feed(chunk));
+	}
+	found.extend(scanner.finish());
+	assert_eq!(found, vec![0]);
+	let set = SignatureSet::new(vec![signature.clone()]);
+	let mut scanner = StreamScanner::new(&set);
+	let mut found = Vec::new();
+	for chunk in data.chunks(3) {
+		found.extend(scanner.feed(chunk));
+	}
+	found.extend(scanner.finish());
+	assert_eq!(found, vec![StreamMatch { offset: 0, id: 0 }]);
+	assert_eq!(StreamScanner::new(&signature).feed(&data), vec![0, 1 << 20]);
+}
+
+#[test]
+fn test_parallel_scan() {
+	let mut data: Vec<u8> = (0..20000u32).map(|i| (i.wrapping_mul(2654435761) >> 24) as u8).collect();
+	for pos in [0, 57, 63, 4093, 8190, 16383, 19994].iter() {
+		data[*pos..*pos + 6].copy_from_slice(&[0xDE, 0xAD, 0x00, 0x00, 0xBE, 0xEF]);
+	}
+
+	// Matches that start at the end of a shard extend into the next one
+	let signature = signature!("DE AD [0-2] BE EF");
+	let expected: Vec<usize> = signature.scan_all(&data).collect();
+	assert_eq!(expected.len(), 7);
+
+	let path = std::env::temp_dir().join(format!("skidscan-test-parallel-scan-{}", std::process::id()));
+	std::fs::write(&path, &data).unwrap();
+	let file = FileScanner::open(&path).unwrap().chunk_size(1000);
+	let mapped = unsafe { FileScanner::open(&path).unwrap().mmap(true) };
+	let expected_file: Vec<u64> = expected.iter().map(|offset| *offset as u64).collect();
+
+	for threads in [1, 4].iter() {
+		for shard_size in [1, 3, 64, 4096, 1 << 20].iter() {
+			let scanner = ParallelScanner::new().threads(*threads).shard_size(*shard_size);
+			assert_eq!(scanner.scan_all(&signature, &data), expected, "{} threads, shard size {}", threads, shard_size);
+			assert_eq!(scanner.scan_unique(&signature, &data), signature.scan_unique(&data));
+			assert_eq!(scanner.scan_unique(&signature, &data[..60]), Ok(0));
+			assert_eq!(scanner.scan_unique(&signature, &data[1..60]), Err(UniqueScanError::NotFound));
+			assert_eq!(scanner.scan_file_all(&file, &signature).unwrap(), expected_file, "{} threads, shard size {}", threads, shard_size);
+			assert_eq!(scanner.scan_file_all(&mapped, &signature).unwrap(), expected_file);
+		}
+	}
+
+	assert_eq!(ParallelScanner::new().scan_all(&signature, &[]), Vec::<usize>::new());
+
+	// Shards overlap by at most 1 MiB, like the chunks of a serial file scan
+	let signature = Signature::from(vec![SigElement::Byte(SigByte::exact(0xAA)), SigElement::Gap { min: 0, max: usize::MAX }, SigElement::Byte(SigByte::exact(0xBB))]);
+	let mut data = vec![0; 3 << 20];
+	data[0] = 0xAA;
+	data[1000] = 0xBB;
+	data[1 << 20] = 0xAA;
+	data[(3 << 20) - 1] = 0xBB;
+	std::fs::write(&path, &data).unwrap();
+	let file = FileScanner::open(&path).unwrap().chunk_size(64 << 10);
+	assert_eq!(file.scan_all(&signature).unwrap(), vec![0]);
+	assert_eq!(ParallelScanner::new().shard_size(64 << 10).scan_file_all(&file, &signature).unwrap(), vec![0]);
+	assert_eq!(ParallelScanner::new().shard_size(64 << 10).scan_all(&signature, &data), vec![0]);
+	assert_eq!(ParallelScanner::new().shard_size(4 << 20).scan_all(&signature, &data), vec![0, 1 << 20]);
+	std::fs::remove_file(&path).unwrap();
+}
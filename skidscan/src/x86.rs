@@ -0,0 +1,299 @@
+//! A length decoder for x86 and x86-64 instructions, which finds the operands that change when code is relocated or relinked
+
+/// The instruction set of the code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Arch {
+	/// 32-bit x86
+	X86,
+
+	/// x86-64
+	X86_64,
+}
+impl Default for Arch {
+	/// The instruction set of this process
+	#[inline]
+	fn default() -> Self {
+		if cfg!(target_pointer_width = "64") {
+			Arch::X86_64
+		} else {
+			Arch::X86
+		}
+	}
+}
+
+/// What an operand of an instruction holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum OperandKind {
+	/// A displacement relative to the end of the instruction, e.g. of `call rel32`
+	Relative,
+
+	/// A memory displacement relative to the end of the instruction, e.g. `[rip+disp32]`
+	RipRelative,
+
+	/// An absolute address, e.g. `[disp32]` without a base register, `moffs` or a far pointer
+	Absolute,
+
+	/// A memory displacement from a register, e.g. `[rbp-8]`
+	Displacement,
+
+	/// An immediate value
+	Immediate,
+}
+
+/// An operand of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Operand {
+	/// The offset of the operand in the instruction
+	pub(crate) offset: usize,
+	pub(crate) len: usize,
+	pub(crate) kind: OperandKind,
+}
+impl Operand {
+	/// Reads the operand's value from the instruction, zero extended
+	pub(crate) fn value(&self, instruction: &[u8]) -> u64 {
+		instruction[self.offset..self.offset + self.len]
+			.iter()
+			.rev()
+			.fold(0, |value, byte| (value << 8) | *byte as u64)
+	}
+}
+
+/// A decoded instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Instruction {
+	pub(crate) len: usize,
+	pub(crate) displacement: Option<Operand>,
+	pub(crate) immediate: Option<Operand>,
+}
+
+/// The immediate operand of an opcode
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Imm {
+	None,
+	/// imm8
+	Byte,
+	/// imm16
+	Word,
+	/// imm16 or imm32, depending on the operand size
+	Z,
+	/// imm16, imm32 or imm64, depending on the operand size
+	V,
+	/// rel8
+	Rel8,
+	/// rel16 or rel32
+	RelZ,
+	/// An absolute address, the size of the address size
+	Moffs,
+	/// A far pointer, ptr16:16 or ptr16:32
+	Far,
+	/// `enter imm16, imm8`
+	Enter,
+	/// imm8 or imm16/32 for `test`, which is only in group 3 when ModRM.reg is 0 or 1
+	Group3(bool),
+	/// imm8 for 0F 3A, and imm32 for XOP map A
+	Dword,
+}
+
+/// Decodes the instruction at the start of `bytes`
+///
+/// Returns `None` if the instruction is invalid, or doesn't fit in `bytes`
+pub(crate) fn decode(bytes: &[u8], arch: Arch) -> Option<Instruction> {
+	let long = arch == Arch::X86_64;
+	let mut pos = 0;
+
+	let mut operand_16 = false;
+	let mut address_short = false;
+	loop {
+		match *bytes.get(pos)? {
+			0x66 => operand_16 = true,
+			0x67 => address_short = true,
+			0xF0 | 0xF2 | 0xF3 | 0x26 | 0x2E | 0x36 | 0x3E | 0x64 | 0x65 => {}
+			_ => break,
+		}
+		pos += 1;
+	}
+
+	let mut rex_w = false;
+	if long && bytes.get(pos)? & 0xF0 == 0x40 {
+		rex_w = bytes[pos] & 0x08 != 0;
+		pos += 1;
+	}
+
+	let opcode = *bytes.get(pos)?;
+	pos += 1;
+
+	// VEX, EVEX and XOP reuse opcodes that are invalid in 64-bit mode, or only take a memory operand in 32-bit mode
+	let next = bytes.get(pos).copied();
+	let register_form = next.map(|next| next >> 6 == 3).unwrap_or(false);
+	let (modrm, imm) = match opcode {
+		0xC4 | 0xC5 | 0x62 if long || register_form => {
+			let (payload, map) = match opcode {
+				0xC5 => (1, 1),
+				0xC4 => (2, next? & 0x1F),
+				_ => (3, next? & 0x07),
+			};
+			pos += payload;
+			let opcode = *bytes.get(pos)?;
+			pos += 1;
+			match map {
+				1 => match opcode {
+					0x77 => (false, Imm::None),
+					0x70..=0x73 | 0xC2 | 0xC4..=0xC6 => (true, Imm::Byte),
+					_ => (true, Imm::None),
+				},
+				2 | 5 | 6 => (true, Imm::None),
+				3 => (true, Imm::Byte),
+				_ => return None,
+			}
+		}
+
+		0x8F if next? & 0x1F >= 8 => {
+			let map = next? & 0x1F;
+			pos += 3;
+			match map {
+				8 => (true, Imm::Byte),
+				9 => (true, Imm::None),
+				0xA => (true, Imm::Dword),
+				_ => return None,
+			}
+		}
+
+		0x0F => {
+			let opcode = *bytes.get(pos)?;
+			pos += 1;
+			match opcode {
+				0x38 => {
+					pos += 1;
+					(true, Imm::None)
+				}
+				0x3A => {
+					pos += 1;
+					(true, Imm::Byte)
+				}
+				// 3DNow!, whose opcode is an imm8 suffix
+				0x0F => (true, Imm::Byte),
+				0x04 | 0x0A | 0x0C | 0x24..=0x27 | 0x36 | 0x39 | 0x3B..=0x3F | 0x7A | 0x7B | 0xA6 | 0xA7 => return None,
+				0x05..=0x09 | 0x0B | 0x0E | 0x30..=0x37 | 0x77 | 0xA0..=0xA2 | 0xA8..=0xAA | 0xC8..=0xCF => (false, Imm::None),
+				0x80..=0x8F => (false, Imm::RelZ),
+				0x70..=0x73 | 0xA4 | 0xAC | 0xBA | 0xC2 | 0xC4..=0xC6 => (true, Imm::Byte),
+				_ => (true, Imm::None),
+			}
+		}
+
+		0x06 | 0x07 | 0x0E | 0x16 | 0x17 | 0x1E | 0x1F | 0x27 | 0x2F | 0x37 | 0x3F | 0x60 | 0x61 | 0x82 | 0x9A | 0xC4 | 0xC5 | 0xCE | 0xD4..=0xD6 | 0xEA
+			if long =>
+		{
+			return None
+		}
+
+		0x00..=0x3F => match opcode & 7 {
+			0..=3 => (true, Imm::None),
+			4 => (false, Imm::Byte),
+			5 => (false, Imm::Z),
+			_ => (false, Imm::None),
+		},
+		0x40..=0x61 | 0x6C..=0x6F | 0x90..=0x99 | 0x9B..=0x9F | 0xA4..=0xA7 | 0xAA..=0xAF | 0xC3 | 0xC9 | 0xCB | 0xCC | 0xCE | 0xCF | 0xD6 | 0xD7 => {
+			(false, Imm::None)
+		}
+		0xEC..=0xEF | 0xF1 | 0xF4 | 0xF5 | 0xF8..=0xFD => (false, Imm::None),
+		0x62 | 0x63 | 0x84..=0x8F | 0xC4 | 0xC5 | 0xD0..=0xD3 | 0xD8..=0xDF | 0xFE | 0xFF => (true, Imm::None),
+		0x68 | 0xA9 => (false, Imm::Z),
+		0x6A | 0xA8 | 0xB0..=0xB7 | 0xCD | 0xD4 | 0xD5 | 0xE4..=0xE7 => (false, Imm::Byte),
+		0x69 | 0x81 | 0xC7 => (true, Imm::Z),
+		0x6B | 0x80 | 0x82 | 0x83 | 0xC0 | 0xC1 | 0xC6 => (true, Imm::Byte),
+		0x70..=0x7F | 0xE0..=0xE3 | 0xEB => (false, Imm::Rel8),
+		0xE8 | 0xE9 => (false, Imm::RelZ),
+		0x9A | 0xEA => (false, Imm::Far),
+		0xA0..=0xA3 => (false, Imm::Moffs),
+		0xB8..=0xBF => (false, Imm::V),
+		0xC2 | 0xCA => (false, Imm::Word),
+		0xC8 => (false, Imm::Enter),
+		0xF6 => (true, Imm::Group3(false)),
+		0xF7 => (true, Imm::Group3(true)),
+
+		// Prefixes are consumed above
+		_ => return None,
+	};
+
+	let z = if operand_16 { 2 } else { 4 };
+	let mut displacement = None;
+	let mut imm = imm;
+	if modrm {
+		let modrm = *bytes.get(pos)?;
+		pos += 1;
+		let (md, reg, rm) = (modrm >> 6, (modrm >> 3) & 7, modrm & 7);
+
+		if let Imm::Group3(z) = imm {
+			imm = match (reg, z) {
+				(0 | 1, false) => Imm::Byte,
+				(0 | 1, true) => Imm::Z,
+				_ => Imm::None,
+			};
+		} else if opcode == 0xC7 && modrm == 0xF8 {
+			// xbegin rel16/32
+			imm = Imm::RelZ;
+		}
+
+		if md != 3 {
+			let (len, kind) = if !long && address_short {
+				match (md, rm) {
+					(0, 6) => (2, OperandKind::Absolute),
+					(1, _) => (1, OperandKind::Displacement),
+					(2, _) => (2, OperandKind::Displacement),
+					_ => (0, OperandKind::Displacement),
+				}
+			} else {
+				let no_base = if rm == 4 {
+					let sib = *bytes.get(pos)?;
+					pos += 1;
+					md == 0 && sib & 7 == 5
+				} else {
+					false
+				};
+				match (md, rm) {
+					(0, 5) if long => (4, OperandKind::RipRelative),
+					(0, 5) => (4, OperandKind::Absolute),
+					(0, _) if no_base => (4, OperandKind::Absolute),
+					(1, _) => (1, OperandKind::Displacement),
+					(2, _) => (4, OperandKind::Displacement),
+					_ => (0, OperandKind::Displacement),
+				}
+			};
+			if len != 0 {
+				displacement = Some(Operand { offset: pos, len, kind });
+				pos += len;
+			}
+		}
+	}
+
+	let (len, kind) = match imm {
+		Imm::None | Imm::Group3(_) => (0, OperandKind::Immediate),
+		Imm::Byte => (1, OperandKind::Immediate),
+		Imm::Word => (2, OperandKind::Immediate),
+		Imm::Dword => (4, OperandKind::Immediate),
+		Imm::Z => (z, OperandKind::Immediate),
+		Imm::V if rex_w => (8, OperandKind::Immediate),
+		Imm::V => (z, OperandKind::Immediate),
+		Imm::Enter => (3, OperandKind::Immediate),
+		Imm::Rel8 => (1, OperandKind::Relative),
+		Imm::RelZ if long => (4, OperandKind::Relative),
+		Imm::RelZ => (z, OperandKind::Relative),
+		Imm::Moffs => (
+			match (long, address_short) {
+				(true, false) => 8,
+				(true, true) | (false, false) => 4,
+				(false, true) => 2,
+			},
+			OperandKind::Absolute,
+		),
+		Imm::Far => (z + 2, OperandKind::Absolute),
+	};
+	let immediate = if len != 0 { Some(Operand { offset: pos, len, kind }) } else { None };
+	pos += len;
+
+	// The longest valid instruction is 15 bytes
+	if pos > 15 || pos > bytes.len() {
+		return None;
+	}
+	Some(Instruction { len: pos, displacement, immediate })
+}